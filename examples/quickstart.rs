@@ -19,23 +19,21 @@ mod errors {
 
 use errors::*;
 
-fn main() {
-    if let Err(ref e) = run() {
-        println!("error: {}", e);
-
-        for e in e.iter().skip(1) {
-            println!("caused by: {}", e);
-        }
-
-        // The backtrace is not always generated. Try to run this example
-        // with `RUST_BACKTRACE=1`.
-        if let Some(backtrace) = e.backtrace() {
-            println!("backtrace: {:?}", backtrace);
-        }
-
-        ::std::process::exit(1);
-    }
-}
+// Generates a `main` function which calls `run`. If it fails, it prints the
+// error, each of its causes and the backtrace to stderr, and exits with the
+// exit code of the error kind, which is 1 unless it was given one with
+// `exit_code()`. This is the same as:
+//
+// fn main() {
+//     if let Err(ref e) = run() {
+//         eprint!("{}", e.display_chain());
+//         ::std::process::exit(e.exit_code());
+//     }
+// }
+//
+// The backtrace is not always generated. Try to run this example with
+// `RUST_BACKTRACE=1`.
+quick_main!(run);
 
 // Most functions will return the `Result` type, imported from the
 // `errors` module. It is a typedef of the standard `Result` type
@@ -10,6 +10,18 @@ error_chain! {
     }
 }
 
+mod thin {
+    error_chain! {
+        options {
+            thin;
+        }
+        errors {
+            AVariant
+            Another
+        }
+    }
+}
+
 fn main() {
     println!("Memory usage in bytes");
     println!("---------------------");
@@ -23,18 +35,36 @@ fn main() {
     println!("    State: {}", size_of::<error_chain::State>());
     #[cfg(feature = "backtrace")]
     {
-        let state = error_chain::State {
+        let state: error_chain::State = error_chain::State {
             next_error: None,
+            context: Vec::new(),
+            location: None,
             backtrace: None,
+            link_backtrace: None,
         };
         println!("      State.next_error: {}", size_of_val(&state.next_error));
+        println!("      State.context: {}", size_of_val(&state.context));
+        println!("      State.location: {}", size_of_val(&state.location));
         println!("      State.backtrace: {}", size_of_val(&state.backtrace));
+        println!("      State.link_backtrace: {}", size_of_val(&state.link_backtrace));
     }
     #[cfg(not(feature = "backtrace"))]
     {
-        let state = error_chain::State {
+        let state: error_chain::State = error_chain::State {
             next_error: None,
+            context: Vec::new(),
+            location: None,
         };
         println!("      State.next_error: {}", size_of_val(&state.next_error));
+        println!("      State.context: {}", size_of_val(&state.context));
+        println!("      State.location: {}", size_of_val(&state.location));
     }
+
+    println!();
+    println!("With the `thin` option");
+    println!("----------------------");
+    println!("Result<()>: {}", size_of::<thin::Result<()>>());
+    println!("  Error: {}", size_of::<thin::Error>());
+    println!("    (ErrorKind, State): {}",
+             size_of::<(thin::ErrorKind, error_chain::State)>());
 }
@@ -0,0 +1,343 @@
+//! Filtering of the frames of the backtraces printed in reports.
+
+use std::fmt;
+
+use Backtrace;
+
+/// The prefixes of the frames hidden by default: the frames from
+/// error-chain itself, from the `backtrace` crate and from the standard
+/// library and the runtime.
+const DEFAULT_DENY: &'static [&'static str] = &["error_chain::",
+                                                "backtrace::",
+                                                "std::",
+                                                "core::",
+                                                "alloc::",
+                                                "__rust_",
+                                                "__rustc::",
+                                                "rust_begin_unwind",
+                                                "_start",
+                                                "__libc_start",
+                                                "__scrt_common_main",
+                                                "BaseThreadInitThunk",
+                                                "RtlUserThreadStart"];
+
+/// The methods hidden by default: the ones through which the errors
+/// generated by `error_chain!` are created and converted, which live in the
+/// crate of the user.
+const DEFAULT_DENY_METHODS: &'static [&'static str] = &["from_kind",
+                                                        "ChainedError::new",
+                                                        "From::from",
+                                                        "Into::into",
+                                                        "ResultExt::chain_err",
+                                                        "ResultExt::chain_err_with",
+                                                        "OptionExt::ok_or_chain"];
+
+/// Decides which frames of a backtrace are printed.
+///
+/// A frame is matched against the path of its symbols, without the hashes
+/// added by the compiler. For trait implementations, the path is the one of
+/// the type, so `error_chain::` matches both `error_chain::make_backtrace`
+/// and `<error_chain::State as core::default::Default>::default`, or the
+/// one of the trait when the type is a generic parameter, as in
+/// `<T as core::convert::Into<U>>::into`. The frame is hidden if one of its
+/// symbols starts with a denied prefix or is a denied method, unless one of
+/// them also starts with an allowed prefix. Frames without symbol names are
+/// always printed.
+///
+/// The default filter hides the frames from error-chain, the `backtrace`
+/// crate and the standard library, which make up most of a backtrace, and
+/// the methods which create and convert the generated errors:
+///
+/// ```
+/// use error_chain::BacktraceFilter;
+///
+/// // Also hide the frames of the `tokio` crate, but keep the ones of
+/// // `std::thread`.
+/// let filter = BacktraceFilter::default().deny("tokio::").allow("std::thread::");
+/// assert!(!filter.is_allowed("tokio::runtime::run"));
+/// assert!(filter.is_allowed("std::thread::spawn"));
+/// assert!(!filter.is_allowed("std::rt::lang_start"));
+/// assert!(!filter.is_allowed("app::errors::Error::from_kind"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BacktraceFilter {
+    allow: Vec<String>,
+    deny: Vec<String>,
+    deny_methods: Vec<String>,
+}
+
+impl BacktraceFilter {
+    /// Creates a filter which prints all the frames.
+    pub fn empty() -> BacktraceFilter {
+        BacktraceFilter {
+            allow: vec![],
+            deny: vec![],
+            deny_methods: vec![],
+        }
+    }
+
+    /// Prints the frames whose symbols start with `prefix`, even if they
+    /// also match a denied prefix.
+    pub fn allow<S: Into<String>>(mut self, prefix: S) -> BacktraceFilter {
+        self.allow.push(prefix.into());
+        self
+    }
+
+    /// Hides the frames whose symbols start with `prefix`.
+    pub fn deny<S: Into<String>>(mut self, prefix: S) -> BacktraceFilter {
+        self.deny.push(prefix.into());
+        self
+    }
+
+    /// Hides the frames of the method `method`, whatever the crate it is
+    /// in. The method is given with its type or trait, as in `From::from`,
+    /// or alone, as in `from_kind`.
+    pub fn deny_method<S: Into<String>>(mut self, method: S) -> BacktraceFilter {
+        self.deny_methods.push(method.into());
+        self
+    }
+
+    /// Returns whether a frame with a symbol named `name` is printed.
+    pub fn is_allowed(&self, name: &str) -> bool {
+        self.is_frame_allowed(Some(name).into_iter())
+    }
+
+    fn is_frame_allowed<'a, I>(&self, names: I) -> bool
+        where I: Iterator<Item = &'a str>
+    {
+        let mut denied = false;
+        for name in names {
+            let name = SymbolPath::new(name);
+            if self.allow.iter().any(|prefix| name.path.starts_with(&**prefix)) {
+                return true;
+            }
+            denied |= self.deny.iter().any(|prefix| name.path.starts_with(&**prefix));
+            denied |= self.deny_methods.iter().any(|method| name.is_method(method));
+        }
+        !denied
+    }
+
+    /// Returns a value which prints the frames of `backtrace` allowed by
+    /// this filter.
+    pub fn display<'a>(&'a self, backtrace: &'a Backtrace) -> FilteredBacktrace<'a> {
+        FilteredBacktrace {
+            backtrace: backtrace,
+            filter: self,
+        }
+    }
+}
+
+impl Default for BacktraceFilter {
+    fn default() -> BacktraceFilter {
+        let filter = DEFAULT_DENY.iter()
+            .fold(BacktraceFilter::empty(), |filter, prefix| filter.deny(*prefix));
+        DEFAULT_DENY_METHODS.iter().fold(filter, |filter, method| filter.deny_method(*method))
+    }
+}
+
+/// The parts of a symbol name which are matched by a `BacktraceFilter`.
+struct SymbolPath {
+    /// The path matched against the prefixes.
+    path: String,
+    /// The last two segments of the path of the function, without generic
+    /// arguments, e.g. `From::from` or `Error::from_kind`.
+    method: String,
+}
+
+impl SymbolPath {
+    fn new(name: &str) -> SymbolPath {
+        let name = strip_hashes(name);
+        // `<Type as Trait>::method`
+        if name.starts_with('<') {
+            if let Some(close) = matching_bracket(&name) {
+                let inner = &name[1..close];
+                let rest = &name[close + 1..];
+                let (ty, function) = match split_as(inner) {
+                    Some((ty, trait_)) => (ty, format!("{}{}", trait_, rest)),
+                    None => (inner, format!("{}{}", inner, rest)),
+                };
+                let ty = strip_references(ty);
+                // Generic parameters such as `T`, and types such as `fn()`,
+                // are not paths.
+                let path = if is_path(ty) {
+                    ty.to_string()
+                } else {
+                    strip_references(&function).to_string()
+                };
+                return SymbolPath {
+                    path: path,
+                    method: last_segments(&function),
+                };
+            }
+        }
+        SymbolPath {
+            method: last_segments(&name),
+            path: name,
+        }
+    }
+
+    fn is_method(&self, method: &str) -> bool {
+        self.method == method ||
+        (self.method.ends_with(method) &&
+         self.method[..self.method.len() - method.len()].ends_with("::"))
+    }
+}
+
+/// Removes the `[0123abcd]` crate disambiguators of v0 symbols and the
+/// `::h0123abcd` suffix of legacy symbols.
+fn strip_hashes(name: &str) -> String {
+    let mut stripped = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(start) = rest.find('[') {
+        stripped.push_str(&rest[..start]);
+        match rest[start..].find(']') {
+            Some(end) if rest[start + 1..start + end].chars().all(|c| c.is_digit(16)) => {
+                rest = &rest[start + end + 1..];
+            }
+            _ => {
+                stripped.push('[');
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    stripped.push_str(rest);
+    if let Some(hash) = stripped.rfind("::h") {
+        if stripped.len() - hash == 19 && stripped[hash + 3..].chars().all(|c| c.is_digit(16)) {
+            stripped.truncate(hash);
+        }
+    }
+    stripped
+}
+
+/// Returns whether the character at `i` closes angle brackets, and is not
+/// the end of a `->` arrow.
+fn is_closing(s: &str, i: usize) -> bool {
+    s[i..].starts_with('>') && !s[..i].ends_with('-')
+}
+
+/// Returns the index of the `>` closing the `<` at the start of `name`.
+fn matching_bracket(name: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in name.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' if is_closing(name, i) => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits `Type as Trait` at the ` as ` outside of generic arguments.
+fn split_as(inner: &str) -> Option<(&str, &str)> {
+    let mut depth = 0;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' if is_closing(inner, i) => depth -= 1,
+            ' ' if depth == 0 && inner[i..].starts_with(" as ") => {
+                return Some((&inner[..i], &inner[i + 4..]));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns whether `ty` starts with a path, as in `std::string::String`.
+fn is_path(ty: &str) -> bool {
+    match ty.find("::") {
+        Some(i) => ty[..i].chars().all(|c| c.is_alphanumeric() || c == '_'),
+        None => false,
+    }
+}
+
+/// Removes the references, pointers and `dyn` in front of a type.
+fn strip_references(mut ty: &str) -> &str {
+    loop {
+        let stripped = ty.trim_start_matches(|c| c == '&' || c == '<' || c == '(' || c == '*')
+            .trim_start_matches("mut ")
+            .trim_start_matches("const ")
+            .trim_start_matches("dyn ");
+        if stripped.len() == ty.len() {
+            return ty;
+        }
+        ty = stripped;
+    }
+}
+
+/// Returns the last two segments of `path`, without generic arguments.
+fn last_segments(path: &str) -> String {
+    let mut plain = String::with_capacity(path.len());
+    let mut depth = 0;
+    for (i, c) in path.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' if is_closing(path, i) => depth -= 1,
+            _ if depth == 0 => plain.push(c),
+            _ => {}
+        }
+    }
+    let mut segments = plain.rsplit("::");
+    match (segments.next(), segments.next()) {
+        (Some(method), Some(ty)) => format!("{}::{}", ty, method),
+        (Some(method), None) => method.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// A backtrace printed with some of its frames hidden, as returned by
+/// `BacktraceFilter::display`.
+///
+/// The frames keep their index in the full backtrace, so that hidden frames
+/// show up as gaps in the numbering.
+pub struct FilteredBacktrace<'a> {
+    backtrace: &'a Backtrace,
+    filter: &'a BacktraceFilter,
+}
+
+#[cfg(feature = "backtrace")]
+impl<'a> fmt::Display for FilteredBacktrace<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(fmt, "stack backtrace:"));
+        for (i, frame) in self.backtrace.frames().iter().enumerate() {
+            let names: Vec<String> = frame.symbols()
+                .iter()
+                .filter_map(|symbol| symbol.name().map(|name| name.to_string()))
+                .collect();
+            if !self.filter.is_frame_allowed(names.iter().map(|name| &**name)) {
+                continue;
+            }
+            if frame.symbols().is_empty() {
+                try!(writeln!(fmt, "{:4}: {:?}", i, frame.ip()));
+            }
+            for (j, symbol) in frame.symbols().iter().enumerate() {
+                if j == 0 {
+                    try!(write!(fmt, "{:4}: ", i));
+                } else {
+                    try!(write!(fmt, "      "));
+                }
+                match symbol.name() {
+                    Some(name) => try!(writeln!(fmt, "{}", name)),
+                    None => try!(writeln!(fmt, "<unknown>")),
+                }
+                if let (Some(file), Some(line)) = (symbol.filename(), symbol.lineno()) {
+                    try!(writeln!(fmt, "             at {}:{}", file.display(), line));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "backtrace"))]
+impl<'a> fmt::Display for FilteredBacktrace<'a> {
+    fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result {
+        Ok(())
+    }
+}
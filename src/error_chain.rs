@@ -8,11 +8,49 @@ macro_rules! error_chain_processed {
     ) => {
         error_chain_processed! {
             types {
-                Error, ErrorKind, ResultExt, Result;
+                Error, ErrorKind, ResultExt, OptionExt, Result;
             }
             $( $rest )*
         }
     };
+    // With `OptionExt` trait and `Result` wrapper.
+    (
+        types {
+            $error_name:ident, $error_kind_name:ident,
+            $result_ext_name:ident, $option_ext_name:ident,
+            $result_name:ident;
+        }
+        $( $rest: tt )*
+    ) => {
+        error_chain_processed! {
+            types {
+                $error_name, $error_kind_name,
+                $result_ext_name, $result_name;
+            }
+            $( $rest )*
+        }
+
+        // The OptionExt trait defines the `ok_or_chain` method.
+
+        /// Additional methods for `Option`, for easy interaction with this crate.
+        pub trait $option_ext_name<T> {
+            /// If the `Option` is `None` then `ok_or_chain` evaluates the
+            /// closure, which returns *some type that can be converted to
+            /// `ErrorKind`*, then returns a new error of this kind, with a
+            /// fresh backtrace.
+            fn ok_or_chain<F, EK>(self, callback: F) -> ::std::result::Result<T, $error_name>
+                where F: FnOnce() -> EK,
+                      EK: Into<$error_kind_name>;
+        }
+
+        impl<T> $option_ext_name<T> for ::std::option::Option<T> {
+            fn ok_or_chain<F, EK>(self, callback: F) -> ::std::result::Result<T, $error_name>
+                where F: FnOnce() -> EK,
+                      EK: Into<$error_kind_name> {
+                self.ok_or_else(move || $error_name::from_kind(callback().into()))
+            }
+        }
+    };
     // With `Result` wrapper.
     (
         types {
@@ -38,9 +76,16 @@ macro_rules! error_chain_processed {
             $result_ext_name:ident;
         }
 
+        options {
+            derives [ $( $derive:ident )* ]
+            repr [ $( $repr:ident )* ]
+            msg [ $( $msg:ident )* ]
+            error_box [ $error_box:ident ]
+        }
+
         links {
             $( $link_variant:ident ( $link_error_path:path, $link_kind_path:path )
-               $( #[$meta_links:meta] )*; ) *
+               $( $link_mode:ident )* $( #[$meta_links:meta] )*; ) *
         }
 
         foreign_links {
@@ -53,79 +98,230 @@ macro_rules! error_chain_processed {
         }
 
     ) => {
-        /// The Error type.
-        ///
-        /// This struct is made of three things:
-        ///
-        /// - an `ErrorKind` which is used to determine the type of the error.
-        /// - a backtrace, generated when the error is created.
-        /// - an error chain, used for the implementation of `Error::cause()`.
-        #[derive(Debug)]
-        pub struct $error_name(
-            // The members must be `pub` for `links`.
-            /// The kind of the error.
-            #[doc(hidden)]
-            pub $error_kind_name,
-            /// Contains the error chain and the backtrace.
-            #[doc(hidden)]
-            pub $crate::State,
-        );
+        error_chain_repr! {
+            struct ($( $repr )*) [$( $derive )*] $error_box $error_name, $error_kind_name
+        }
 
         impl $crate::ChainedError for $error_name {
             type ErrorKind = $error_kind_name;
+            type ErrorBox = $crate::$error_box;
+
+            error_chain_repr! {
+                chained_error ($( $repr )*) $error_box $error_name, $error_kind_name
+            }
 
-            fn new(kind: $error_kind_name, state: $crate::State) -> $error_name {
-                $error_name(kind, state)
+            fn kind_name(&self) -> &'static str {
+                self.kind().name()
             }
 
-            impl_extract_backtrace!($error_name
-                                    $error_kind_name
-                                    $([$link_error_path, $(#[$meta_links])*])*);
+            fn exit_code(&self) -> i32 {
+                self.kind().exit_code()
+            }
+
+            fn iter(&self) -> $crate::ErrorChainIter {
+                self.iter()
+            }
+
+            fn backtrace(&self) -> Option<&$crate::Backtrace> {
+                self.backtrace()
+            }
+
+            fn context(&self) -> &[(&'static str, String)] {
+                self.context()
+            }
+
+            fn location(&self) -> Option<$crate::Location> {
+                self.location()
+            }
+
+            fn extract_state<'a>(e: &'a (::std::error::Error + Send + 'static))
+                -> Option<&'a $crate::AnyState> {
+                if let Some(e) = e.downcast_ref::<$error_name>() {
+                    return Some($crate::ChainedError::state(e) as &$crate::AnyState);
+                }
+                if let Some(state) = $crate::linked_state(e) {
+                    return Some(state);
+                }
+                $(
+                    $( #[$meta_links] )*
+                    {
+                        if let Some(e) = e.downcast_ref::<$link_error_path>() {
+                            return Some($crate::ChainedError::state(e) as &$crate::AnyState);
+                        }
+                    }
+                ) *
+                None
+            }
         }
 
         #[allow(dead_code)]
         impl $error_name {
             /// Constructs an error from a kind, and generates a backtrace.
             pub fn from_kind(kind: $error_kind_name) -> $error_name {
-                $error_name(
-                    kind,
-                    $crate::State::default(),
-                )
+                $crate::ChainedError::new(kind, $crate::State::default())
+            }
+
+            /// Constructs an error of the given kind, whose cause is an
+            /// `AggregateError` holding all the `errors`.
+            pub fn aggregate<K, I>(kind: K, errors: I) -> $error_name
+                where K: Into<$error_kind_name>,
+                      I: IntoIterator,
+                      I::Item: $crate::IntoErrorBox<$crate::$error_box>
+            {
+                let errors = errors.into_iter()
+                    .map($crate::box_error)
+                    .collect();
+                let aggregate = $crate::AggregateError::<$crate::$error_box>::new(errors);
+                let state = $crate::State::new::<$error_name>($crate::box_error(aggregate));
+                $crate::ChainedError::new(kind.into(), state)
+            }
+
+            /// Returns the values of `results` if they are all `Ok`.
+            /// Otherwise, evaluates the closure and returns an error of
+            /// this kind which aggregates all the errors.
+            pub fn collect_all<I, T, E, F, EK>(results: I, callback: F)
+                                               -> ::std::result::Result<Vec<T>, $error_name>
+                where I: IntoIterator<Item = ::std::result::Result<T, E>>,
+                      E: $crate::IntoErrorBox<$crate::$error_box>,
+                      F: FnOnce() -> EK,
+                      EK: Into<$error_kind_name>
+            {
+                let mut values = Vec::new();
+                let mut errors = Vec::new();
+                for result in results {
+                    match result {
+                        Ok(value) => values.push(value),
+                        Err(e) => errors.push(e),
+                    }
+                }
+                if errors.is_empty() {
+                    Ok(values)
+                } else {
+                    Err($error_name::aggregate(callback(), errors))
+                }
             }
 
             /// Returns the kind of the error.
             pub fn kind(&self) -> &$error_kind_name {
-                &self.0
+                $crate::ChainedError::kind(self)
             }
 
+            error_chain_log! { error_impl $error_name }
+
             /// Iterates over the error chain.
             pub fn iter(&self) -> $crate::ErrorChainIter {
                 $crate::ErrorChainIter(Some(self))
             }
 
+            /// Returns the last error of the chain, which is this error if
+            /// it has no cause.
+            pub fn root_cause(&self) -> &(::std::error::Error + 'static) {
+                self.iter().root_cause().unwrap_or(self)
+            }
+
+            /// Returns the first error of the chain, starting with this
+            /// one, which is of type `T`.
+            pub fn find_cause<T: ::std::error::Error + 'static>(&self) -> Option<&T> {
+                self.iter().find_cause::<T>()
+            }
+
+            /// Returns the number of errors in the chain, including this
+            /// one.
+            pub fn depth(&self) -> usize {
+                self.iter().depth()
+            }
+
+            /// Returns whether `f` returns `true` for any error of the
+            /// chain, starting with this one.
+            pub fn any_cause<F>(&self, f: F) -> bool
+                where F: FnMut(&(::std::error::Error + 'static)) -> bool
+            {
+                self.iter().any_cause(f)
+            }
+
             /// Returns the backtrace associated with this error.
             pub fn backtrace(&self) -> Option<&$crate::Backtrace> {
-                self.1.backtrace()
+                $crate::ChainedError::state(self).backtrace()
+            }
+
+            /// Returns the backtrace of the site where this error was chained
+            /// to its cause, if link backtraces are enabled.
+            pub fn link_backtrace(&self) -> Option<&$crate::Backtrace> {
+                $crate::ChainedError::state(self).link_backtrace()
+            }
+
+            /// Returns an object which implements `Display` for printing the
+            /// full error chain and the backtrace.
+            pub fn display_chain(&self) -> $crate::DisplayChain<$error_name> {
+                $crate::DisplayChain::new(self)
+            }
+
+            /// Returns the key/value context attached to this error.
+            pub fn context(&self) -> &[(&'static str, String)] {
+                &$crate::ChainedError::state(self).context
+            }
+
+            /// Returns the value of the first context entry for `key`.
+            pub fn context_value(&self, key: &str) -> Option<&str> {
+                $crate::ChainedError::state(self).context_value(key)
+            }
+
+            /// Returns the location in the source code where this error was
+            /// created, if it was created by `bail!`, `ensure!` or
+            /// `chain_err!`.
+            pub fn location(&self) -> Option<$crate::Location> {
+                $crate::ChainedError::state(self).location
             }
         }
 
         impl ::std::error::Error for $error_name {
             fn description(&self) -> &str {
-                self.0.description()
+                self.kind().description()
             }
 
             fn cause(&self) -> Option<&::std::error::Error> {
-                match self.1.next_error {
-                    Some(ref c) => Some(&**c),
+                match $crate::ChainedError::state(self).next_error {
+                    Some(ref c) => Some($crate::ErrorBox::as_error(c)),
                     None => {
-                        match self.0 {
+                        match *self.kind() {
                             $(
                                 $(#[$meta_foreign_links])*
                                 $error_kind_name::$foreign_link_variant(ref foreign_err) => {
                                     foreign_err.cause()
                                 }
                             ) *
-                            _ => None
+                            $(
+                                $(#[$meta_links])*
+                                $error_kind_name::$link_variant(ref kind) => {
+                                    kind.contained_error()
+                                }
+                            ) *
+                            #[allow(unreachable_patterns)]
+                            ref kind => kind.contained_error(),
+                        }
+                    }
+                }
+            }
+
+            fn source(&self) -> Option<&(::std::error::Error + 'static)> {
+                match $crate::ChainedError::state(self).next_error {
+                    Some(ref c) => Some($crate::ErrorBox::as_error(c)),
+                    None => {
+                        match *self.kind() {
+                            $(
+                                $(#[$meta_foreign_links])*
+                                $error_kind_name::$foreign_link_variant(ref foreign_err) => {
+                                    foreign_err.source()
+                                }
+                            ) *
+                            $(
+                                $(#[$meta_links])*
+                                $error_kind_name::$link_variant(ref kind) => {
+                                    kind.contained_error()
+                                }
+                            ) *
+                            #[allow(unreachable_patterns)]
+                            ref kind => kind.contained_error(),
                         }
                     }
                 }
@@ -134,19 +330,15 @@ macro_rules! error_chain_processed {
 
         impl ::std::fmt::Display for $error_name {
             fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-                ::std::fmt::Display::fmt(&self.0, f)
+                ::std::fmt::Display::fmt(self.kind(), f)
             }
         }
 
         $(
-            $(#[$meta_links])*
-            impl From<$link_error_path> for $error_name {
-                fn from(e: $link_error_path) -> Self {
-                    $error_name(
-                        $error_kind_name::$link_variant(e.0),
-                        e.1,
-                    )
-                }
+            error_chain_link_from! {
+                ($( $link_mode )*)
+                $( #[$meta_links] )*
+                $error_name, $error_kind_name, $link_variant, $link_error_path
             }
         ) *
 
@@ -167,23 +359,31 @@ macro_rules! error_chain_processed {
             }
         }
 
-        impl<'a> From<&'a str> for $error_name {
-            fn from(s: &'a str) -> Self {
-                $error_name::from_kind(s.into())
+        $(
+            impl<'a> From<&'a str> for $error_name {
+                fn from(s: &'a str) -> Self {
+                    $error_name::from_kind($error_kind_name::$msg(::std::borrow::Cow::Owned(s.to_owned())))
+                }
+            }
+
+            impl From<String> for $error_name {
+                fn from(s: String) -> Self {
+                    $error_name::from_kind($error_kind_name::$msg(::std::borrow::Cow::Owned(s)))
+                }
             }
-        }
 
-        impl From<String> for $error_name {
-            fn from(s: String) -> Self {
-                $error_name::from_kind(s.into())
+            impl From<::std::borrow::Cow<'static, str>> for $error_name {
+                fn from(s: ::std::borrow::Cow<'static, str>) -> Self {
+                    $error_name::from_kind($error_kind_name::$msg(s))
+                }
             }
-        }
+        ) *
 
         impl ::std::ops::Deref for $error_name {
             type Target = $error_kind_name;
 
             fn deref(&self) -> &Self::Target {
-                &self.0
+                self.kind()
             }
         }
 
@@ -192,21 +392,27 @@ macro_rules! error_chain_processed {
         // --------------
 
         quick_error! {
+            ERROR $error_name;
+
             /// The kind of an error.
-            #[derive(Debug)]
+            #[derive(Debug $(, $derive )*)]
             pub enum $error_kind_name {
 
-                /// A convenient variant for String.
-                Msg(s: String) {
-                    description(&s)
-                    display("{}", s)
-                }
+                $(
+                    /// A convenient variant for string messages. Static
+                    /// strings are stored without allocating.
+                    $msg(s: ::std::borrow::Cow<'static, str>) {
+                        description(&s)
+                        display("{}", s)
+                    }
+                ) *
 
                 $(
                     $(#[$meta_links])*
                     $link_variant(e: $link_kind_path) {
                         description(e.description())
                         display("{}", e)
+                        exit_code(e.exit_code())
                     }
                 ) *
 
@@ -222,6 +428,12 @@ macro_rules! error_chain_processed {
             }
         }
 
+        impl From<$error_name> for Box<::std::error::Error + Send> {
+            fn from(e: $error_name) -> Self {
+                Box::new(e)
+            }
+        }
+
         $(
             $(#[$meta_links])*
             impl From<$link_kind_path> for $error_kind_name {
@@ -231,21 +443,29 @@ macro_rules! error_chain_processed {
             }
         ) *
 
-        impl<'a> From<&'a str> for $error_kind_name {
-            fn from(s: &'a str) -> Self {
-                $error_kind_name::Msg(s.to_string())
+        $(
+            impl<'a> From<&'a str> for $error_kind_name {
+                fn from(s: &'a str) -> Self {
+                    $error_kind_name::$msg(::std::borrow::Cow::Owned(s.to_owned()))
+                }
             }
-        }
 
-        impl From<String> for $error_kind_name {
-            fn from(s: String) -> Self {
-                $error_kind_name::Msg(s)
+            impl From<String> for $error_kind_name {
+                fn from(s: String) -> Self {
+                    $error_kind_name::$msg(::std::borrow::Cow::Owned(s))
+                }
             }
-        }
+
+            impl From<::std::borrow::Cow<'static, str>> for $error_kind_name {
+                fn from(s: ::std::borrow::Cow<'static, str>) -> Self {
+                    $error_kind_name::$msg(s)
+                }
+            }
+        ) *
 
         impl From<$error_name> for $error_kind_name {
             fn from(e: $error_name) -> Self {
-                e.0
+                $crate::ChainedError::into_parts(e).0
             }
         }
 
@@ -260,17 +480,46 @@ macro_rules! error_chain_processed {
             fn chain_err<F, EK>(self, callback: F) -> ::std::result::Result<T, $error_name>
                 where F: FnOnce() -> EK,
                       EK: Into<$error_kind_name>;
+
+            /// Like `chain_err`, but also attaches the `context` key/value
+            /// pairs to the new error. The values are only formatted if the
+            /// `Result` is an `Err`.
+            fn chain_err_with<F, EK>(self,
+                                     callback: F,
+                                     context: &[(&'static str, &::std::fmt::Display)])
+                                     -> ::std::result::Result<T, $error_name>
+                where F: FnOnce() -> EK,
+                      EK: Into<$error_kind_name>;
+
+            error_chain_log! { result_ext_trait $error_name }
         }
 
-        impl<T, E> $result_ext_name<T, E> for ::std::result::Result<T, E> where E: ::std::error::Error + Send + 'static {
+        impl<T, E> $result_ext_name<T, E> for ::std::result::Result<T, E>
+            where E: $crate::IntoErrorBox<$crate::$error_box>
+        {
             fn chain_err<F, EK>(self, callback: F) -> ::std::result::Result<T, $error_name>
                 where F: FnOnce() -> EK,
                       EK: Into<$error_kind_name> {
                 self.map_err(move |e| {
-                    let state = $crate::State::new::<$error_name>(Box::new(e), );
+                    let state = $crate::State::new::<$error_name>($crate::box_error(e));
                     $crate::ChainedError::new(callback().into(), state)
                 })
             }
+
+            fn chain_err_with<F, EK>(self,
+                                     callback: F,
+                                     context: &[(&'static str, &::std::fmt::Display)])
+                                     -> ::std::result::Result<T, $error_name>
+                where F: FnOnce() -> EK,
+                      EK: Into<$error_kind_name> {
+                self.map_err(move |e| {
+                    let mut state = $crate::State::new::<$error_name>($crate::box_error(e));
+                    state.add_context(context);
+                    $crate::ChainedError::new(callback().into(), state)
+                })
+            }
+
+            error_chain_log! { result_ext_impl $error_name }
         }
 
 
@@ -282,53 +531,256 @@ macro_rules! error_chain_processed {
 #[macro_export]
 macro_rules! error_chain_processing {
     (
-        ({}, $b:tt, $c:tt, $d:tt)
+        ({}, $b:tt, $c:tt, $d:tt, $e:tt)
         types $content:tt
         $( $tail:tt )*
     ) => {
         error_chain_processing! {
-            ($content, $b, $c, $d)
+            ($content, $b, $c, $d, $e)
             $($tail)*
         }
     };
     (
-        ($a:tt, {}, $c:tt, $d:tt)
+        ($a:tt, {}, $c:tt, $d:tt, $e:tt)
         links $content:tt
         $( $tail:tt )*
     ) => {
         error_chain_processing! {
-            ($a, $content, $c, $d)
+            ($a, $content, $c, $d, $e)
             $($tail)*
         }
     };
     (
-        ($a:tt, $b:tt, {}, $d:tt)
+        ($a:tt, $b:tt, {}, $d:tt, $e:tt)
         foreign_links $content:tt
         $( $tail:tt )*
     ) => {
         error_chain_processing! {
-            ($a, $b, $content, $d)
+            ($a, $b, $content, $d, $e)
             $($tail)*
         }
     };
     (
-        ($a:tt, $b:tt, $c:tt, {})
+        ($a:tt, $b:tt, $c:tt, {}, $e:tt)
         errors $content:tt
         $( $tail:tt )*
     ) => {
         error_chain_processing! {
-            ($a, $b, $c, $content)
+            ($a, $b, $c, $content, $e)
             $($tail)*
         }
     };
-    ( ($a:tt, $b:tt, $c:tt, $d:tt) ) => {
+    (
+        ($a:tt, $b:tt, $c:tt, $d:tt, {})
+        options $content:tt
+        $( $tail:tt )*
+    ) => {
+        error_chain_processing! {
+            ($a, $b, $c, $d, $content)
+            $($tail)*
+        }
+    };
+    ( ($a:tt, $b:tt, $c:tt, $d:tt, { $( $e:tt )* }) ) => {
+        error_chain_options! {
+            (derives [] repr [] msg [Msg] error_box [SendBox])
+            (types $a links $b foreign_links $c errors $d)
+            $( $e )*
+        }
+    };
+}
+
+/// Internal macro used to normalize the `options` section.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! error_chain_options {
+    (
+        (derives [ $( $derive:ident )* ] repr [ $( $repr:ident )* ] msg [ $( $msg:ident )* ]
+         error_box [ $error_box:ident ])
+        (types $a:tt links $b:tt foreign_links $c:tt errors $d:tt)
+    ) => {
         error_chain_processed! {
             types $a
+            options {
+                derives [ $( $derive )* ]
+                repr [ $( $repr )* ]
+                msg [ $( $msg )* ]
+                error_box [ $error_box ]
+            }
             links $b
             foreign_links $c
             errors $d
         }
     };
+    // The errors of the chain are `Sync`, so that `Error` is `Sync`.
+    (
+        (derives $derives:tt repr $repr:tt msg $msg:tt error_box [SendBox])
+        $sections:tt
+        sync;
+        $( $tail:tt )*
+    ) => {
+        error_chain_options! {
+            (derives $derives repr $repr msg $msg error_box [SyncBox])
+            $sections
+            $( $tail )*
+        }
+    };
+    // `clone` already implies `sync`.
+    (
+        (derives $derives:tt repr $repr:tt msg $msg:tt error_box [SharedBox])
+        $sections:tt
+        sync;
+        $( $tail:tt )*
+    ) => {
+        error_chain_options! {
+            (derives $derives repr $repr msg $msg error_box [SharedBox])
+            $sections
+            $( $tail )*
+        }
+    };
+    // `Error` and `ErrorKind` derive `Clone`, and the errors of the chain
+    // are shared between the clones.
+    (
+        (derives [ $( $derive:ident )* ] repr $repr:tt msg $msg:tt error_box $error_box:tt)
+        $sections:tt
+        clone;
+        $( $tail:tt )*
+    ) => {
+        error_chain_options! {
+            (derives [ $( $derive )* Clone ] repr $repr msg $msg error_box [SharedBox])
+            $sections
+            $( $tail )*
+        }
+    };
+    // `Error` is a pointer to the kind and the state.
+    (
+        (derives $derives:tt repr [] msg $msg:tt error_box $error_box:tt)
+        $sections:tt
+        thin;
+        $( $tail:tt )*
+    ) => {
+        error_chain_options! {
+            (derives $derives repr [thin] msg $msg error_box $error_box)
+            $sections
+            $( $tail )*
+        }
+    };
+    // The variant for string messages is renamed.
+    (
+        (derives $derives:tt repr $repr:tt msg [Msg] error_box $error_box:tt)
+        $sections:tt
+        msg($msg:ident);
+        $( $tail:tt )*
+    ) => {
+        error_chain_options! {
+            (derives $derives repr $repr msg [$msg] error_box $error_box)
+            $sections
+            $( $tail )*
+        }
+    };
+    // There is no variant for string messages.
+    (
+        (derives $derives:tt repr $repr:tt msg [Msg] error_box $error_box:tt)
+        $sections:tt
+        no_msg;
+        $( $tail:tt )*
+    ) => {
+        error_chain_options! {
+            (derives $derives repr $repr msg [] error_box $error_box)
+            $sections
+            $( $tail )*
+        }
+    };
+}
+
+/// Internal macro used to generate the parts of `Error` which depend on its
+/// representation.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! error_chain_repr {
+    (struct () [ $( $derive:ident )* ] $error_box:ident $error_name:ident, $error_kind_name:ident) => {
+        /// The Error type.
+        ///
+        /// This struct is made of three things:
+        ///
+        /// - an `ErrorKind` which is used to determine the type of the error.
+        /// - a backtrace, generated when the error is created.
+        /// - an error chain, used for the implementation of `Error::cause()`
+        ///   and `Error::source()`.
+        #[derive(Debug $(, $derive )*)]
+        pub struct $error_name(
+            /// The kind of the error.
+            #[doc(hidden)]
+            pub $error_kind_name,
+            /// Contains the error chain and the backtrace.
+            #[doc(hidden)]
+            pub $crate::State<$crate::$error_box>,
+        );
+    };
+    (chained_error () $error_box:ident $error_name:ident, $error_kind_name:ident) => {
+        fn new(kind: $error_kind_name, state: $crate::State<$crate::$error_box>) -> $error_name {
+            static REGISTERED: ::std::sync::atomic::AtomicBool =
+                ::std::sync::atomic::AtomicBool::new(false);
+            $crate::register_chained_error::<$error_name>(&REGISTERED);
+            $error_name(kind, state)
+        }
+
+        fn kind(&self) -> &$error_kind_name {
+            &self.0
+        }
+
+        fn state(&self) -> &$crate::State<$crate::$error_box> {
+            &self.1
+        }
+
+        fn state_mut(&mut self) -> &mut $crate::State<$crate::$error_box> {
+            &mut self.1
+        }
+
+        fn into_parts(self) -> ($error_kind_name, $crate::State<$crate::$error_box>) {
+            (self.0, self.1)
+        }
+    };
+    (struct (thin) [ $( $derive:ident )* ] $error_box:ident $error_name:ident, $error_kind_name:ident) => {
+        /// The Error type.
+        ///
+        /// This struct is a pointer to three things, so that `Result`s
+        /// stay small:
+        ///
+        /// - an `ErrorKind` which is used to determine the type of the error.
+        /// - a backtrace, generated when the error is created.
+        /// - an error chain, used for the implementation of `Error::cause()`
+        ///   and `Error::source()`.
+        #[derive(Debug $(, $derive )*)]
+        pub struct $error_name(
+            /// The kind of the error, and the error chain and the backtrace.
+            #[doc(hidden)]
+            pub Box<($error_kind_name, $crate::State<$crate::$error_box>)>,
+        );
+    };
+    (chained_error (thin) $error_box:ident $error_name:ident, $error_kind_name:ident) => {
+        fn new(kind: $error_kind_name, state: $crate::State<$crate::$error_box>) -> $error_name {
+            static REGISTERED: ::std::sync::atomic::AtomicBool =
+                ::std::sync::atomic::AtomicBool::new(false);
+            $crate::register_chained_error::<$error_name>(&REGISTERED);
+            $error_name(Box::new((kind, state)))
+        }
+
+        fn kind(&self) -> &$error_kind_name {
+            &(self.0).0
+        }
+
+        fn state(&self) -> &$crate::State<$crate::$error_box> {
+            &(self.0).1
+        }
+
+        fn state_mut(&mut self) -> &mut $crate::State<$crate::$error_box> {
+            &mut (self.0).1
+        }
+
+        fn into_parts(self) -> ($error_kind_name, $crate::State<$crate::$error_box>) {
+            *self.0
+        }
+    };
 }
 
 /// This macro is used for handling of duplicated and out-of-order fields. For
@@ -337,52 +789,83 @@ macro_rules! error_chain_processing {
 macro_rules! error_chain {
     ( $( $block_name:ident { $( $block_content:tt )* } )* ) => {
         error_chain_processing! {
-            ({}, {}, {}, {})
+            ({}, {}, {}, {}, {})
             $($block_name { $( $block_content )* })*
         }
     };
 }
 
-/// Macro used to manage the `backtrace` feature.
-///
-/// See
-/// https://www.reddit.com/r/rust/comments/57virt/hey_rustaceans_got_an_easy_question_ask_here/da5r4ti/?context=3
-/// for more details.
-#[macro_export]
+/// Internal macro used to generate the `From` impl of a link, depending on
+/// its mode.
 #[doc(hidden)]
-#[cfg(feature = "backtrace")]
-macro_rules! impl_extract_backtrace {
-    ($error_name: ident
-     $error_kind_name: ident
-     $([$link_error_path: path, $(#[$meta_links: meta])*])*) => {
-        fn extract_backtrace(e: &(::std::error::Error + Send + 'static))
-            -> Option<::std::sync::Arc<$crate::Backtrace>> {
-            if let Some(e) = e.downcast_ref::<$error_name>() {
-                return e.1.backtrace.clone();
-            }
-            $(
-                $( #[$meta_links] )*
-                {
-                    if let Some(e) = e.downcast_ref::<$link_error_path>() {
-                        return e.1.backtrace.clone();
-                    }
-                }
-            ) *
-            None
+#[macro_export]
+macro_rules! error_chain_link_from {
+    // The kind and the state of the linked error are reused.
+    (
+        ()
+        $( #[$meta:meta] )*
+        $error_name:ident, $error_kind_name:ident, $link_variant:ident, $link_error_path:path
+    ) => {
+        $(#[$meta])*
+        impl From<$link_error_path> for $error_name {
+            fn from(e: $link_error_path) -> Self {
+                let (kind, state) = $crate::ChainedError::into_parts(e);
+                $crate::ChainedError::new($error_kind_name::$link_variant(kind), state.convert())
+            }
         }
-    }
+    };
+    // The linked error is also kept in the chain, as a `LinkedError`.
+    (
+        (chained)
+        $( #[$meta:meta] )*
+        $error_name:ident, $error_kind_name:ident, $link_variant:ident, $link_error_path:path
+    ) => {
+        $(#[$meta])*
+        impl From<$link_error_path> for $error_name {
+            fn from(e: $link_error_path) -> Self {
+                let message = e.to_string();
+                let description = ::std::error::Error::description(&e).to_string();
+                let (kind, state) = $crate::ChainedError::into_parts(e);
+                let linked = $crate::LinkedError::new(message, description, state);
+                let state = $crate::State::new::<$error_name>($crate::box_error(linked));
+                $crate::ChainedError::new($error_kind_name::$link_variant(kind), state)
+            }
+        }
+    };
 }
 
-/// Macro used to manage the `backtrace` feature.
-///
-/// See
-/// https://www.reddit.com/r/rust/comments/57virt/hey_rustaceans_got_an_easy_question_ask_here/da5r4ti/?context=3
-/// for more details.
+/// Generates the methods of the `log` feature in the generated items.
+#[cfg(feature = "log")]
+#[doc(hidden)]
 #[macro_export]
+macro_rules! error_chain_log {
+    (result_ext_trait $error_name:ident) => {
+        /// If the `Result` is an `Err`, logs the error with all its causes
+        /// and the backtrace, if any, as a single record of the given
+        /// `level`. The `Result` is returned unchanged.
+        fn log_err(self, level: $crate::log::Level) -> Self;
+    };
+    (result_ext_impl $error_name:ident) => {
+        fn log_err(self, level: $crate::log::Level) -> Self {
+            if let Err(ref e) = self {
+                $crate::log_error::<$error_name>(e, level, module_path!());
+            }
+            self
+        }
+    };
+    (error_impl $error_name:ident) => {
+        /// Logs this error with all its causes and the backtrace, if any,
+        /// as a single record of the given `level`.
+        pub fn log(&self, level: $crate::log::Level) {
+            $crate::log_error::<$error_name>(self, level, module_path!());
+        }
+    };
+}
+
+/// Generates the methods of the `log` feature in the generated items.
+#[cfg(not(feature = "log"))]
 #[doc(hidden)]
-#[cfg(not(feature = "backtrace"))]
-macro_rules! impl_extract_backtrace {
-    ($error_name: ident
-     $error_kind_name: ident
-     $([$link_error_path: path, $(#[$meta_links: meta])*])*) => {}
+#[macro_export]
+macro_rules! error_chain_log {
+    ($( $tt:tt )*) => {};
 }
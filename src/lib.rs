@@ -45,7 +45,7 @@
 //! * Conversions between error types are done in an automatic and
 //!   consistent way - `From` conversion behavior is never specified
 //!   explicitly.
-//! * Errors implement Send.
+//! * Errors implement Send, and optionally Sync.
 //! * Errors can carry backtraces.
 //!
 //! Similar to other libraries like [error-type] and [quick-error],
@@ -113,6 +113,13 @@
 //!         Error, ErrorKind, ResultExt, Result;
 //!     }
 //!
+//!     // With the `OptionExt` trait, which is only generated by default or
+//!     // when it is named explicitly:
+//!     //
+//!     // types {
+//!     //     Error, ErrorKind, ResultExt, OptionExt, Result;
+//!     // }
+//!
 //!     // Without the `Result` wrapper:
 //!     //
 //!     // types {
@@ -127,9 +134,13 @@
 //!     //
 //!     // Optionally, some attributes can be added to a variant.
 //!     //
+//!     // With the `chained` mode, the linked error is also kept as a
+//!     // separate entry of the error chain.
+//!     //
 //!     // This section can be empty.
 //!     links {
 //!         Another(other_error::Error, other_error::ErrorKind) #[cfg(unix)];
+//!         // Yet(yet_another::Error, yet_another::ErrorKind) chained;
 //!     }
 //!
 //!     // Automatic conversions between this error chain and other
@@ -147,12 +158,26 @@
 //!     }
 //!
 //!     // Define additional `ErrorKind` variants. The syntax here is
-//!     // the same as `quick_error!`, but the `from()` and `cause()`
-//!     // syntax is not supported.
+//!     // the same as `quick_error!`. The `from()` clauses implement
+//!     // `From` for both `ErrorKind` and `Error`, so `?` converts these
+//!     // types directly, and the error given to `cause()` is the cause of
+//!     // the `Error`, unless it was created by `chain_err`.
+//!     //
+//!     // Optionally, a stable code can be given to a variant with
+//!     // `code()`, for use in metrics and alerts, and the exit code used
+//!     // by `quick_main!` with `exit_code()`.
 //!     errors {
 //!         InvalidToolchainName(t: String) {
 //!             description("invalid toolchain name")
 //!             display("invalid toolchain name: '{}'", t)
+//!             code("E0001")
+//!             exit_code(2)
+//!         }
+//!         InvalidNumber(e: ::std::num::ParseIntError) {
+//!             description("invalid number")
+//!             display("invalid number")
+//!             from()
+//!             cause(e)
 //!         }
 //!     }
 //! }
@@ -161,13 +186,19 @@
 //! ```
 //!
 //! Each section, `types`, `links`, `foreign_links`, and `errors` may
-//! be omitted if it is empty.
+//! be omitted if it is empty. An `options` section, described below, can
+//! also be given.
 //!
 //! This populates the module with a number of definitions,
 //! the most important of which are the `Error` type
 //! and the `ErrorKind` type. An example of generated code can be found in the
 //! [example_generated](example_generated) module.
 //!
+//! `ErrorKind::name()` returns the name of the variant, e.g.
+//! `"InvalidToolchainName"` or `"Msg"`, and `ErrorKind::code()` returns the
+//! code given to the variant with `code()`, if any. Unlike the `display`
+//! text, they are suitable as machine-readable identifiers.
+//!
 //! ## Returning new errors
 //!
 //! Introducing new error chains, with a string message:
@@ -202,6 +233,36 @@
 //! strings and `ErrorKind` have `From` conversions to turn them into
 //! `Error`.
 //!
+//! String messages are stored in the `ErrorKind::Msg` variant as a
+//! `Cow<'static, str>`: `String`s, e.g. from `format!`, are moved into the
+//! error, and borrowed strings are copied, including the ones returned by
+//! the closure of `chain_err`. The string literals given to `chain_err!`
+//! are not copied, and neither are `Cow::Borrowed` messages.
+//!
+//! The variant can be renamed with the `msg(Name)` option, or removed
+//! with the `no_msg` option for a closed set of kinds. Without it, strings
+//! can't be converted to `Error`, so `bail!`, `ensure!` and `chain_err`
+//! must be given an `ErrorKind`:
+//!
+//! ```
+//! # #[macro_use] extern crate error_chain;
+//! # fn main() {}
+//! error_chain! {
+//!     options {
+//!         no_msg;
+//!     }
+//!     errors {
+//!         NotFound(name: String) {
+//!             display("not found: {}", name)
+//!         }
+//!     }
+//! }
+//!
+//! fn find(name: &str) -> Result<()> {
+//!     bail!(ErrorKind::NotFound(name.to_string()))
+//! }
+//! ```
+//!
 //! When the error is emitted inside a `try!` macro or behind the
 //! `?` operator, the explicit conversion isn't needed; `try!` will
 //! automatically convert `Err(ErrorKind)` to `Err(Error)`. So the
@@ -244,6 +305,21 @@
 //! }
 //! ```
 //!
+//! `ensure!` is the conditional counterpart of `bail!`: it returns the
+//! error only if the condition does not hold.
+//!
+//! ```
+//! # #[macro_use] extern crate error_chain;
+//! # fn main() {}
+//! # error_chain! { errors { FooError } }
+//! fn foo(n: u32) -> Result<()> {
+//!     ensure!(n < 10, ErrorKind::FooError);
+//!     ensure!(n % 2 == 0, "odd number: {}", n);
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
 //! ## Chaining errors
 //!
 //! To extend the error chain:
@@ -260,11 +336,65 @@
 //! ```
 //!
 //! `chain_err` can be called on any `Result` type where the contained
-//! error type implements `std::error::Error + Send + 'static`.  If
-//! the `Result` is an `Err` then `chain_err` evaluates the closure,
-//! which returns *some type that can be converted to `ErrorKind`*,
-//! boxes the original error to store as the cause, then returns a new
-//! error containing the original error.
+//! error type implements `std::error::Error + Send + 'static` (and `Sync`
+//! with the `sync` option).  If the `Result` is an `Err` then
+//! `chain_err` evaluates the closure, which returns *some type that can
+//! be converted to `ErrorKind`*, boxes the original error to store as
+//! the cause, then returns a new error containing the original error.
+//!
+//! `chain_err_with` additionally attaches key/value pairs to the new
+//! error. The values are only formatted if the `Result` is an `Err`:
+//!
+//! ```
+//! # #[macro_use] extern crate error_chain;
+//! # fn main() {}
+//! # error_chain! {}
+//! # fn load(path: &str) -> Result<()> { Err("not found".into()) }
+//! # fn test() {
+//! # let path = "config.toml";
+//! # let request_id = 42;
+//! let res = load(path).chain_err_with(|| "unable to load the configuration",
+//!                                     &[("path", &path), ("request", &request_id)]);
+//! if let Err(ref e) = res {
+//!     assert_eq!(e.context_value("path"), Some("config.toml"));
+//! }
+//! # }
+//! ```
+//!
+//! The context is printed by `display_chain` below each error of the chain.
+//!
+//! The `chain_err!` macro is another shorthand for `chain_err`, which
+//! records the location of the call in the new error, as `bail!` does.
+//! Even without backtraces, this tells where each error of the chain
+//! originated:
+//!
+//! ```
+//! # #[macro_use] extern crate error_chain;
+//! # fn main() {}
+//! # error_chain! {}
+//! # fn do_something() -> Result<()> { unimplemented!() }
+//! # fn test() -> Result<()> {
+//! chain_err!(do_something(), "something went wrong")?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Missing values enter the chain the same way with `ok_or_chain`, defined
+//! on `Option` by the `OptionExt` trait:
+//!
+//! ```
+//! # #[macro_use] extern crate error_chain;
+//! # fn main() {}
+//! # error_chain! {}
+//! # fn test() -> Result<()> {
+//! # let args: Vec<String> = vec![];
+//! let first = args.first().ok_or_chain(|| "missing argument")?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! If the `Option` is `None` then `ok_or_chain` evaluates the closure and
+//! returns a new error of this kind, with a fresh backtrace.
 //!
 //! ## Matching errors
 //!
@@ -342,6 +472,13 @@
 //! old error is discarded; there is no "cause" created from the
 //! original error.
 //!
+//! A link declared with the `chained` mode, as in
+//! `Utils(rustup_utils::Error, rustup_utils::ErrorKind) chained;`, keeps
+//! the original error in the chain instead: the new `Error` still has the
+//! `ErrorKind::Utils` kind, and its cause is a `LinkedError` with the
+//! message, the causes, the context and the location of the original
+//! error. The backtrace is still passed through.
+//!
 //! ## Backtraces
 //!
 //! If the `RUST_BACKTRACE` environment variable is set to anything
@@ -350,11 +487,262 @@
 //! and `chain_err` invocations of compatible types. To read the
 //! backtrace just call the `backtrace()` method.
 //!
+//! Only the stack is captured when the error is created: the symbols are
+//! resolved the first time the backtrace is accessed, so errors which are
+//! recovered from stay cheap.
+//!
+//! The environment variable is only read once, when the first error is
+//! created. Programs which want to control backtraces independently of
+//! panics can instead set a `BacktracePolicy` at startup:
+//!
+//! ```
+//! use error_chain::BacktracePolicy;
+//!
+//! // Generate a backtrace for one error out of 100.
+//! error_chain::set_backtrace_policy(BacktracePolicy::Sampled(100));
+//! ```
+//!
+//! The backtrace of the earliest error is usually the most useful one, but
+//! when errors are passed between threads or futures, the stack where they
+//! are chained tells more. With `set_link_backtraces(true)`, each error
+//! created by `chain_err` also records a backtrace of its own, following the
+//! policy, which is returned by its `link_backtrace()` method. Reports print
+//! all of them with `e.display_chain().all_backtraces(true)`.
+//!
 //! Backtrace generation can be disabled by turning off the `backtrace` feature.
 //!
+//! ## Boxed errors
+//!
+//! The generated `Error` can be converted into a `Box<Error + Send>`, and,
+//! with the `sync` option, into a `Box<Error + Send + Sync>`, so `?`
+//! works in functions returning these types. The causes are still reached
+//! through `source()`, and the backtrace can be recovered from the trait
+//! object with `error_chain::extract_backtrace`, which recognizes the
+//! errors of all the types generated by `error_chain!` in the process:
+//!
+//! ```
+//! # #[macro_use] extern crate error_chain;
+//! # error_chain! {}
+//! # fn main() {
+//! use std::error::Error as StdError;
+//!
+//! fn plugin() -> std::result::Result<(), Box<StdError + Send>> {
+//!     Err(Error::from("plugin failed"))?
+//! }
+//!
+//! let e = plugin().unwrap_err();
+//! if let Some(backtrace) = error_chain::extract_backtrace(&*e) {
+//!     println!("{:?}", backtrace);
+//! }
+//! # }
+//! ```
+//!
+//! ## Sync errors
+//!
+//! By default, the errors of the chain are only required to be `Send`, so
+//! the generated `Error` is not `Sync`. With the `sync` option, the chain
+//! stores `std::error::Error + Send + Sync` objects instead: `chain_err`
+//! then requires the chained error to be `Sync`, as do `foreign_links`, and
+//! in exchange the generated `Error` is `Sync`, so it can be shared in an
+//! `Arc` or converted into a `Box<std::error::Error + Send + Sync>`:
+//!
+//! ```
+//! # #[macro_use] extern crate error_chain;
+//! # fn main() {
+//! error_chain! {
+//!     options {
+//!         sync;
+//!     }
+//! }
+//!
+//! let e: Box<std::error::Error + Send + Sync> = Error::from("error!").into();
+//! # }
+//! ```
+//!
+//! The option only applies to the types generated by this invocation, so
+//! other `error_chain!` invocations, including the ones of other crates,
+//! keep accepting errors which aren't `Sync`. A type with the `sync`
+//! option can be linked into one without it, but not the other way around.
+//!
+//! ## Cloning errors
+//!
+//! The `clone` option makes the generated `Error` and `ErrorKind` derive
+//! `Clone`, so failures can be cached or sent to several consumers. It
+//! implies the `sync` option, and the errors of the chain are stored in an
+//! `Arc`, so that they are shared between clones:
+//!
+//! ```
+//! # #[macro_use] extern crate error_chain;
+//! # fn main() {
+//! error_chain! {
+//!     options {
+//!         clone;
+//!     }
+//! }
+//!
+//! let e = Error::from("error!");
+//! let copy = e.clone();
+//! # }
+//! ```
+//!
+//! All the variants of `ErrorKind` must be `Clone`, so the errors from
+//! `foreign_links` must be `Clone`, and the errors from `links` must be
+//! generated with the `clone` option too. The errors generated with the
+//! `clone` option can be linked into errors without it, like any other.
+//!
+//! ## Small errors
+//!
+//! By default, `Error` holds the `ErrorKind` and the chain inline, which
+//! makes `Result`s quite large. With the `thin` option, `Error` is a single
+//! pointer to both of them instead, at the cost of an allocation for each
+//! error:
+//!
+//! ```
+//! # #[macro_use] extern crate error_chain;
+//! # fn main() {
+//! error_chain! {
+//!     options {
+//!         thin;
+//!     }
+//! }
+//!
+//! assert_eq!(std::mem::size_of::<Error>(), std::mem::size_of::<usize>());
+//! match *Error::from("error!").kind() {
+//!     ErrorKind::Msg(_) => { }
+//! }
+//! # }
+//! ```
+//!
+//! The kind can't be matched with `Error(kind, _)` patterns then, so it is
+//! matched through `kind()` instead. Everything else works the same.
+//!
+//! ## Serialization
+//!
+//! With the `serde` feature, `ErrorSnapshot::new` takes a serializable
+//! snapshot of an error, its causes and its backtrace, e.g. to send it to
+//! another process. Once deserialized, the snapshot implements
+//! `std::error::Error` with the same chain as the original error.
+//!
+//! ## Logging
+//!
+//! With the `log` feature, `Error::log(level)` and `ResultExt::log_err(level)`
+//! log an error with all its causes and the backtrace, if any, as a single
+//! multi-line record of the `log` crate, without consuming it:
+//!
+//! ```ignore
+//! let config = read_config().log_err(log::Level::Warn).unwrap_or_default();
+//! ```
+//!
+//! The target of the record is the module which defines the error.
+//!
 //! ## Iteration
 //!
 //! The `iter` method returns an iterator over the chain of error boxes.
+//! The errors are followed with `Error::source()`, so errors from other
+//! crates must implement it to be part of the chain.
+//!
+//! The generated `Error` also has shortcuts to search the chain:
+//! `root_cause`, `find_cause`, `depth` and `any_cause`. As the errors are
+//! `'static`, `find_cause` can downcast them, e.g. to find the `io::Error`
+//! which was chained with `chain_err`:
+//!
+//! ```
+//! # #[macro_use] extern crate error_chain;
+//! # fn main() {}
+//! # error_chain! {}
+//! # fn run() -> Result<()> { Ok(()) }
+//! # fn test() {
+//! if let Err(ref e) = run() {
+//!     if let Some(io_error) = e.find_cause::<::std::io::Error>() {
+//!         println!("I/O error: {:?}", io_error.kind());
+//!     }
+//! }
+//! # }
+//! ```
+//!
+//! Note that the errors from `foreign_links` are stored in the
+//! `ErrorKind`, not in the chain, so they are found by matching on the
+//! kind instead.
+//!
+//! ## Reporting errors
+//!
+//! The `display_chain` method returns a report which prints the error,
+//! each of its causes and the backtrace:
+//!
+//! ```
+//! # #[macro_use] extern crate error_chain;
+//! # fn main() {}
+//! # error_chain! {}
+//! # fn run() -> Result<()> { Ok(()) }
+//! # fn test() {
+//! if let Err(ref e) = run() {
+//!     println!("{}", e.display_chain());
+//! }
+//! # }
+//! ```
+//!
+//! The report can be tuned with `indent`, `backtrace`, `all_backtraces`
+//! and `max_depth`, e.g. `e.display_chain().backtrace(false).max_depth(3)`.
+//!
+//! The frames of the backtrace from error-chain itself, from the standard
+//! library, and from the methods which create and convert the generated
+//! errors are hidden by default. Other frames can be hidden, or shown
+//! again, with a `BacktraceFilter`:
+//!
+//! ```
+//! # #[macro_use] extern crate error_chain;
+//! # fn main() {}
+//! # error_chain! {}
+//! # fn run() -> Result<()> { Ok(()) }
+//! # fn test() {
+//! use error_chain::BacktraceFilter;
+//!
+//! if let Err(ref e) = run() {
+//!     let filter = BacktraceFilter::default().deny("tokio::");
+//!     println!("{}", e.display_chain().backtrace_filter(filter));
+//!     // Print all the frames.
+//!     println!("{}", e.display_chain().backtrace_filter(BacktraceFilter::empty()));
+//! }
+//! # }
+//! ```
+//!
+//! The `quick_main!` macro generates a `main` function which does that
+//! for a function returning a `Result`, and exits with the exit code given
+//! to the kind of the error with the `exit_code()` clause in `errors`:
+//!
+//! ```
+//! # #[macro_use] extern crate error_chain;
+//! # error_chain! {}
+//! quick_main!(run);
+//!
+//! fn run() -> Result<()> {
+//!     Ok(())
+//! }
+//! ```
+//!
+//! ## Aggregating errors
+//!
+//! Several independent errors can be reported at once with
+//! `Error::aggregate`, which creates an error of the given kind whose cause
+//! is an `AggregateError` holding all of them. `Error::collect_all` returns
+//! either all the values of an iterator of `Result`s, or such an aggregate
+//! error with all the errors:
+//!
+//! ```
+//! # #[macro_use] extern crate error_chain;
+//! # fn main() {}
+//! # error_chain! {}
+//! fn validate(line: &str) -> Result<u32> {
+//!     line.parse().chain_err(|| format!("invalid line: {}", line))
+//! }
+//!
+//! fn validate_all(lines: &[&str]) -> Result<Vec<u32>> {
+//!     Error::collect_all(lines.iter().map(|line| validate(line)),
+//!                        || "validation failed")
+//! }
+//! ```
+//!
+//! `display_chain` prints the errors of the aggregate as a tree.
 //!
 //! [error-type]: https://github.com/DanielKeep/rust-error-type
 //! [quick-error]: https://github.com/tailhook/quick-error
@@ -362,11 +750,28 @@
 
 #[cfg(feature = "backtrace")]
 extern crate backtrace;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "log")]
+#[macro_use]
+#[doc(hidden)]
+pub extern crate log;
 
+use std::borrow::Cow;
 use std::error;
+use std::fmt;
+use std::io::{self, Write};
 use std::iter::Iterator;
-#[cfg(feature = "backtrace")]
+use std::marker::PhantomData;
+use std::slice;
+use std::ptr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+#[cfg(feature = "backtrace")]
+use std::cell::UnsafeCell;
+#[cfg(feature = "backtrace")]
+use std::sync::Once;
 
 #[cfg(feature = "backtrace")]
 pub use backtrace::Backtrace;
@@ -380,17 +785,52 @@ mod quick_error;
 mod error_chain;
 #[cfg(feature = "example_generated")]
 pub mod example_generated;
+#[cfg(feature = "serde")]
+mod snapshot;
+mod backtrace_filter;
+
+#[cfg(feature = "serde")]
+pub use snapshot::{ErrorSnapshot, FrameSnapshot};
+pub use backtrace_filter::{BacktraceFilter, FilteredBacktrace};
 
-/// Iterator over the error chain using the `Error::cause()` method.
-pub struct ErrorChainIter<'a>(pub Option<&'a error::Error>);
+/// Iterator over the error chain using the `Error::source()` method.
+///
+/// The errors are yielded as `'static` trait objects, so they can be
+/// downcast to their concrete type.
+pub struct ErrorChainIter<'a>(pub Option<&'a (error::Error + 'static)>);
+
+impl<'a> ErrorChainIter<'a> {
+    /// Returns the last error of the chain, i.e. the one which has no
+    /// source.
+    pub fn root_cause(self) -> Option<&'a (error::Error + 'static)> {
+        self.last()
+    }
+
+    /// Returns the first error of the chain which is of type `T`.
+    pub fn find_cause<T: error::Error + 'static>(self) -> Option<&'a T> {
+        self.filter_map(|e| e.downcast_ref::<T>()).next()
+    }
+
+    /// Returns the number of errors in the chain, including the first one.
+    pub fn depth(self) -> usize {
+        self.count()
+    }
+
+    /// Returns whether `f` returns `true` for any error of the chain.
+    pub fn any_cause<F>(mut self, f: F) -> bool
+        where F: FnMut(&'a (error::Error + 'static)) -> bool
+    {
+        self.any(f)
+    }
+}
 
 impl<'a> Iterator for ErrorChainIter<'a> {
-    type Item = &'a error::Error;
+    type Item = &'a (error::Error + 'static);
 
-    fn next<'b>(&'b mut self) -> Option<&'a error::Error> {
+    fn next<'b>(&'b mut self) -> Option<&'a (error::Error + 'static)> {
         match self.0.take() {
             Some(e) => {
-                self.0 = e.cause();
+                self.0 = e.source();
                 Some(e)
             }
             None => None,
@@ -398,86 +838,1068 @@ impl<'a> Iterator for ErrorChainIter<'a> {
     }
 }
 
-/// Returns a backtrace of the current call stack if `RUST_BACKTRACE`
-/// is set to anything but ``0``, and `None` otherwise.  This is used
-/// in the generated error implementations.
+/// A backtrace whose symbols are only resolved when it is first accessed.
+///
+/// Capturing the stack is cheap compared to resolving the symbols, so errors
+/// which are created and then recovered from never pay for the latter.
 #[cfg(feature = "backtrace")]
 #[doc(hidden)]
-pub fn make_backtrace() -> Option<Arc<Backtrace>> {
-    match std::env::var_os("RUST_BACKTRACE") {
-        Some(ref val) if val != "0" => Some(Arc::new(Backtrace::new())),
-        _ => None,
-    }
+pub struct InternalBacktrace {
+    resolved: Once,
+    backtrace: UnsafeCell<Backtrace>,
 }
 
-/// This trait is implemented on all the errors generated by the `error_chain`
-/// macro.
-pub trait ChainedError: error::Error + Send + 'static {
-    /// Associated kind type.
-    type ErrorKind;
+// The backtrace is only mutated once, inside `resolved.call_once`, and no
+// reference to it is handed out before that.
+#[cfg(feature = "backtrace")]
+unsafe impl Sync for InternalBacktrace {}
 
-    /// Creates an error from it's parts.
-    #[doc(hidden)]
-    fn new(kind: Self::ErrorKind, state: State) -> Self;
+#[cfg(feature = "backtrace")]
+impl InternalBacktrace {
+    /// Captures the current call stack, without resolving the symbols.
+    pub fn new() -> InternalBacktrace {
+        InternalBacktrace {
+            resolved: Once::new(),
+            backtrace: UnsafeCell::new(Backtrace::new_unresolved()),
+        }
+    }
 
-    /// Returns the first known backtrace, either from it's State or from one
-    /// of the errors from `foreign_links`.
-    #[cfg(feature = "backtrace")]
-    #[doc(hidden)]
-    fn extract_backtrace(e: &(error::Error + Send + 'static)) -> Option<Arc<Backtrace>>;
+    /// Returns the backtrace, resolving the symbols on the first call.
+    pub fn as_backtrace(&self) -> &Backtrace {
+        self.resolved.call_once(|| unsafe { (*self.backtrace.get()).resolve() });
+        unsafe { &*self.backtrace.get() }
+    }
 }
 
-/// Common state between errors.
-#[derive(Debug)]
-#[doc(hidden)]
-pub struct State {
-    /// Next error in the error chain.
-    pub next_error: Option<Box<error::Error + Send>>,
-    /// Backtrace for the current error.
-    #[cfg(feature = "backtrace")]
-    pub backtrace: Option<Arc<Backtrace>>,
+#[cfg(feature = "backtrace")]
+impl fmt::Debug for InternalBacktrace {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_backtrace(), fmt)
+    }
 }
 
-impl Default for State {
-    fn default() -> State {
-        #[cfg(feature = "backtrace")]
-        let state = State {
-            next_error: None,
-            backtrace: make_backtrace(),
-        };
-        #[cfg(not(feature = "backtrace"))]
-        let state = State { next_error: None };
-        state
-    }
+/// Decides when backtraces are generated for new errors.
+///
+/// The policy is set for the whole process with `set_backtrace_policy`.
+/// It has no effect if the `backtrace` feature is disabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BacktracePolicy {
+    /// Generate a backtrace for every error.
+    Always,
+    /// Never generate a backtrace.
+    Never,
+    /// Generate a backtrace if the `RUST_BACKTRACE` environment variable is
+    /// set to anything but ``0``. The variable is only read once. This is
+    /// the default.
+    Env,
+    /// Generate a backtrace for one error out of `n`.
+    Sampled(usize),
 }
 
-impl State {
-    /// Creates a new State type
-    pub fn new<CE: ChainedError>(e: Box<error::Error + Send>) -> State {
-        #[cfg(feature = "backtrace")]
-        let state = {
-            let backtrace = CE::extract_backtrace(&*e).or_else(make_backtrace);
-            State {
-                next_error: Some(e),
-                backtrace: backtrace,
-            }
-        };
-        #[cfg(not(feature = "backtrace"))]
-        let state = State { next_error: Some(e) };
+const POLICY_ENV: usize = 0;
+const POLICY_ALWAYS: usize = 1;
+const POLICY_NEVER: usize = 2;
+const POLICY_SAMPLED: usize = 3;
 
-        state
+static POLICY: AtomicUsize = AtomicUsize::new(0);
+static SAMPLE_RATE: AtomicUsize = AtomicUsize::new(0);
+#[cfg(feature = "backtrace")]
+static SAMPLE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the policy used to decide whether new errors get a backtrace.
+pub fn set_backtrace_policy(policy: BacktracePolicy) {
+    let policy = match policy {
+        BacktracePolicy::Always => POLICY_ALWAYS,
+        BacktracePolicy::Never => POLICY_NEVER,
+        BacktracePolicy::Env => POLICY_ENV,
+        BacktracePolicy::Sampled(n) => {
+            SAMPLE_RATE.store(n, Ordering::SeqCst);
+            POLICY_SAMPLED
+        }
+    };
+    POLICY.store(policy, Ordering::SeqCst);
+}
+
+/// Returns the current backtrace policy.
+pub fn backtrace_policy() -> BacktracePolicy {
+    match POLICY.load(Ordering::SeqCst) {
+        POLICY_ALWAYS => BacktracePolicy::Always,
+        POLICY_NEVER => BacktracePolicy::Never,
+        POLICY_SAMPLED => BacktracePolicy::Sampled(SAMPLE_RATE.load(Ordering::SeqCst)),
+        _ => BacktracePolicy::Env,
     }
+}
 
-    /// Returns the inner backtrace if present.
-    pub fn backtrace(&self) -> Option<&Backtrace> {
-        #[cfg(feature = "backtrace")]
-        let b = self.backtrace.as_ref().map(|v| &**v);
+static LINK_BACKTRACES: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether each error of a chain records its own backtrace, instead
+/// of only reusing the backtrace of the earliest error.
+///
+/// The backtraces of the links follow the `BacktracePolicy`, and they are
+/// returned by the `link_backtrace()` method of the generated errors.
+pub fn set_link_backtraces(enabled: bool) {
+    LINK_BACKTRACES.store(enabled, Ordering::SeqCst);
+}
+
+/// Returns whether each error of a chain records its own backtrace.
+pub fn link_backtraces() -> bool {
+    LINK_BACKTRACES.load(Ordering::SeqCst)
+}
+
+/// Returns whether `RUST_BACKTRACE` is set to anything but ``0``. The
+/// variable is read on the first call only.
+#[cfg(feature = "backtrace")]
+fn backtrace_env_enabled() -> bool {
+    const ENABLED: usize = 1;
+    const DISABLED: usize = 2;
+    static ENV: AtomicUsize = AtomicUsize::new(0);
+
+    match ENV.load(Ordering::Relaxed) {
+        ENABLED => true,
+        DISABLED => false,
+        _ => {
+            let enabled = match std::env::var_os("RUST_BACKTRACE") {
+                Some(ref val) if val != "0" => true,
+                _ => false,
+            };
+            ENV.store(if enabled { ENABLED } else { DISABLED }, Ordering::Relaxed);
+            enabled
+        }
+    }
+}
+
+/// Returns a backtrace of the current call stack if the current
+/// `BacktracePolicy` asks for one, and `None` otherwise.  This is used
+/// in the generated error implementations.
+#[cfg(feature = "backtrace")]
+#[doc(hidden)]
+pub fn make_backtrace() -> Option<Arc<InternalBacktrace>> {
+    let generate = match backtrace_policy() {
+        BacktracePolicy::Always => true,
+        BacktracePolicy::Never => false,
+        BacktracePolicy::Env => backtrace_env_enabled(),
+        BacktracePolicy::Sampled(0) => false,
+        BacktracePolicy::Sampled(n) => SAMPLE_COUNTER.fetch_add(1, Ordering::Relaxed) % n == 0,
+    };
+    if generate {
+        Some(Arc::new(InternalBacktrace::new()))
+    } else {
+        None
+    }
+}
+
+/// A boxed error from the error chain. The errors are only required to be
+/// `Send`.
+#[doc(hidden)]
+pub type SendBox = Box<error::Error + Send>;
+/// A boxed error from the error chain, for the errors generated with the
+/// `sync` option.
+#[doc(hidden)]
+pub type SyncBox = Box<error::Error + Send + Sync>;
+/// A boxed error from the error chain, shared between the clones of the
+/// errors generated with the `clone` option.
+#[doc(hidden)]
+pub type SharedBox = Arc<error::Error + Send + Sync>;
+
+/// A pointer to an error of the chain, as stored in `State`.
+///
+/// It is implemented for `SendBox`, `SyncBox` and `SharedBox`, which are
+/// chosen by the options of `error_chain!`.
+#[doc(hidden)]
+pub trait ErrorBox: fmt::Debug + Send + 'static {
+    /// Returns the error.
+    fn as_error(&self) -> &(error::Error + Send + 'static);
+}
+
+impl ErrorBox for SendBox {
+    fn as_error(&self) -> &(error::Error + Send + 'static) {
+        match self.downcast_ref::<SharedError>() {
+            Some(shared) => &*shared.0,
+            None => &**self,
+        }
+    }
+}
+
+impl ErrorBox for SyncBox {
+    fn as_error(&self) -> &(error::Error + Send + 'static) {
+        match self.downcast_ref::<SharedError>() {
+            Some(shared) => &*shared.0,
+            None => &**self,
+        }
+    }
+}
+
+impl ErrorBox for SharedBox {
+    fn as_error(&self) -> &(error::Error + Send + 'static) {
+        &**self
+    }
+}
+
+/// Errors which can be stored in the error chain as a `B`.
+///
+/// This is implemented for all `std::error::Error + Send + 'static` types
+/// for `SendBox`, and for the ones which are also `Sync` for `SyncBox` and
+/// `SharedBox`.
+#[doc(hidden)]
+pub trait IntoErrorBox<B>: error::Error + Send + 'static {
+    /// Boxes the error.
+    fn into_error_box(self) -> B;
+}
+
+impl<E: error::Error + Send + 'static> IntoErrorBox<SendBox> for E {
+    fn into_error_box(self) -> SendBox {
+        Box::new(self)
+    }
+}
+
+impl<E: error::Error + Send + Sync + 'static> IntoErrorBox<SyncBox> for E {
+    fn into_error_box(self) -> SyncBox {
+        Box::new(self)
+    }
+}
+
+impl<E: error::Error + Send + Sync + 'static> IntoErrorBox<SharedBox> for E {
+    fn into_error_box(self) -> SharedBox {
+        Arc::new(self)
+    }
+}
+
+/// Converts the errors of the chain of a linked error into the ones of the
+/// error it is linked into, when they are at least as constrained.
+#[doc(hidden)]
+pub trait ConvertErrorBox<B>: ErrorBox {
+    /// Converts the pointer.
+    fn convert(self) -> B;
+}
+
+impl<B: ErrorBox> ConvertErrorBox<B> for B {
+    fn convert(self) -> B {
+        self
+    }
+}
+
+impl ConvertErrorBox<SendBox> for SyncBox {
+    fn convert(self) -> SendBox {
+        self
+    }
+}
+
+impl ConvertErrorBox<SharedBox> for SyncBox {
+    fn convert(self) -> SharedBox {
+        Arc::from(self)
+    }
+}
+
+impl ConvertErrorBox<SendBox> for SharedBox {
+    fn convert(self) -> SendBox {
+        Box::new(SharedError(self))
+    }
+}
+
+impl ConvertErrorBox<SyncBox> for SharedBox {
+    fn convert(self) -> SyncBox {
+        Box::new(SharedError(self))
+    }
+}
+
+/// An error of the chain of an error generated with the `clone` option,
+/// which was linked into an error without it.
+///
+/// The error is still shared with the clones of the linked error.
+/// `ErrorBox::as_error` looks through this wrapper, so it never shows up in
+/// the chain.
+#[derive(Debug)]
+#[doc(hidden)]
+pub struct SharedError(SharedBox);
+
+impl fmt::Display for SharedError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&*self.0, fmt)
+    }
+}
+
+impl error::Error for SharedError {
+    fn description(&self) -> &str {
+        self.0.description()
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        self.0.cause()
+    }
+
+    fn source(&self) -> Option<&(error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
+/// Boxes an error to store it in the error chain. This is used in the
+/// generated error implementations.
+#[doc(hidden)]
+pub fn box_error<B, E: IntoErrorBox<B>>(e: E) -> B {
+    e.into_error_box()
+}
+
+/// This trait is implemented on all the errors generated by the `error_chain`
+/// macro.
+pub trait ChainedError: error::Error + Send + 'static {
+    /// Associated kind type.
+    type ErrorKind: fmt::Debug;
+
+    /// How the errors of the chain are stored.
+    #[doc(hidden)]
+    type ErrorBox: ErrorBox;
+
+    /// Creates an error from it's parts.
+    #[doc(hidden)]
+    fn new(kind: Self::ErrorKind, state: State<Self::ErrorBox>) -> Self;
+
+    /// Returns the kind of the error.
+    fn kind(&self) -> &Self::ErrorKind;
+
+    /// Returns the name of the variant of the kind of the error.
+    fn kind_name(&self) -> &'static str;
+
+    /// Returns the exit code of a program which fails with this error, as
+    /// given by the `exit_code()` clause of its kind. It defaults to 1.
+    fn exit_code(&self) -> i32;
+
+    /// Iterates over the error chain.
+    fn iter(&self) -> ErrorChainIter;
+
+    /// Returns the backtrace associated with this error.
+    fn backtrace(&self) -> Option<&Backtrace>;
+
+    /// Returns an object which implements `Display` for printing the full
+    /// error chain and the backtrace.
+    fn display_chain(&self) -> DisplayChain<Self>
+        where Self: Sized
+    {
+        DisplayChain::new(self)
+    }
+
+    /// Returns the key/value context attached to this error.
+    fn context(&self) -> &[(&'static str, String)];
+
+    /// Returns the location in the source code where this error was created,
+    /// if it was created by `bail!`, `ensure!` or `chain_err!`.
+    fn location(&self) -> Option<Location>;
+
+    /// Returns the state of the error.
+    #[doc(hidden)]
+    fn state(&self) -> &State<Self::ErrorBox>;
+
+    /// Returns the state of the error, mutably.
+    #[doc(hidden)]
+    fn state_mut(&mut self) -> &mut State<Self::ErrorBox>;
+
+    /// Splits the error into its kind and its state.
+    #[doc(hidden)]
+    fn into_parts(self) -> (Self::ErrorKind, State<Self::ErrorBox>);
+
+    /// Returns the state of `e` if it is either an error of this type, a
+    /// `LinkedError` or one of the errors from `links`.
+    #[doc(hidden)]
+    fn extract_state<'a>(e: &'a (error::Error + Send + 'static)) -> Option<&'a AnyState>;
+
+    /// Returns the first known backtrace, either from it's State or from one
+    /// of the errors from `links`.
+    #[cfg(feature = "backtrace")]
+    #[doc(hidden)]
+    fn extract_backtrace(e: &(error::Error + Send + 'static)) -> Option<Arc<InternalBacktrace>> {
+        Self::extract_state(e)
+            .or_else(|| registered_state(e))
+            .and_then(|state| state.shared_backtrace().cloned())
+    }
+}
+
+/// A chained error type, registered in `REGISTRY` when its first error is
+/// created.
+struct Registered {
+    state: for<'a> fn(&'a (error::Error + 'static)) -> Option<&'a AnyState>,
+    next: *mut Registered,
+}
+
+/// The head of the list of the chained error types of the process. Items
+/// are only ever pushed, and are never freed.
+static REGISTRY: AtomicPtr<Registered> = AtomicPtr::new(0 as *mut Registered);
+
+/// Registers `CE`, so that the state of its errors can be found by
+/// `extract_backtrace`, unless `registered` is already set. This is called
+/// each time an error is created.
+#[doc(hidden)]
+#[inline]
+pub fn register_chained_error<CE: ChainedError>(registered: &'static AtomicBool) {
+    if !registered.load(Ordering::Relaxed) && !registered.swap(true, Ordering::SeqCst) {
+        fn state<'a, CE: ChainedError>(e: &'a (error::Error + 'static)) -> Option<&'a AnyState> {
+            e.downcast_ref::<CE>().map(|e| e.state() as &AnyState)
+        }
+
+        let item = Box::into_raw(Box::new(Registered {
+            state: state::<CE>,
+            next: ptr::null_mut(),
+        }));
+        let mut head = REGISTRY.load(Ordering::SeqCst);
+        loop {
+            unsafe { (*item).next = head };
+            match REGISTRY.compare_exchange(head, item, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+    }
+}
+
+/// Returns the state of `e` if it is a `LinkedError` or an error of one of
+/// the registered chained error types.
+fn registered_state<'a>(e: &'a (error::Error + 'static)) -> Option<&'a AnyState> {
+    if let Some(state) = linked_state(e) {
+        return Some(state);
+    }
+    let mut item = REGISTRY.load(Ordering::SeqCst);
+    while let Some(registered) = unsafe { item.as_ref() } {
+        if let Some(state) = (registered.state)(e) {
+            return Some(state);
+        }
+        item = registered.next;
+    }
+    None
+}
+
+/// Returns the backtrace of `e` or of the first of its causes which has
+/// one, if `e` is an error generated by `error_chain!`, or has one in its
+/// chain.
+///
+/// Unlike `ChainedError::backtrace`, this works on trait objects, e.g. after
+/// an error was converted into a `Box<Error + Send + Sync>`. The errors of
+/// every type generated by `error_chain!` in the process are recognized.
+pub fn extract_backtrace<'a>(e: &'a (error::Error + 'static)) -> Option<&'a Backtrace> {
+    let mut current = Some(e);
+    while let Some(e) = current {
+        if let Some(backtrace) = registered_state(e).and_then(|state| state.backtrace()) {
+            return Some(backtrace);
+        }
+        current = e.source();
+    }
+    None
+}
+
+/// Iterator over the error chain, along with the state of the errors which
+/// could be recognized by `T::extract_state`.
+struct Links<'a, T> {
+    next: Option<(&'a (error::Error + 'static), Option<&'a AnyState>)>,
+    marker: PhantomData<T>,
+}
+
+impl<'a, T: ChainedError> Links<'a, T> {
+    fn new(error: &'a (error::Error + Send + 'static)) -> Links<'a, T> {
+        Links {
+            next: Some((error, T::extract_state(error))),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: ChainedError> Iterator for Links<'a, T> {
+    type Item = (&'a (error::Error + 'static), Option<&'a AnyState>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.next.take();
+        if let Some((e, state)) = current {
+            self.next = match state.and_then(|state| state.next_error()) {
+                Some(next) => Some((next as &(error::Error + 'static), T::extract_state(next))),
+                None => e.source().map(|cause| (cause, None)),
+            };
+        }
+        current
+    }
+}
+
+/// A report of a full error chain, as returned by
+/// `ChainedError::display_chain`.
+///
+/// By default, the error is printed as `Error: ...`, followed by a
+/// `Caused by: ...` line for each of its causes and then by the backtrace,
+/// if any. The location and the context attached to the errors of the chain
+/// are printed below each of them, the context as one `key: value` pair per
+/// line.
+///
+/// The errors of an `AggregateError` are printed as a tree: each of them
+/// gets a `Caused by (i/n): ...` line, followed by its own causes indented
+/// by 4 more spaces.
+pub struct DisplayChain<'a, T: 'a + ?Sized> {
+    error: &'a (error::Error + Send + 'static),
+    indent: usize,
+    backtrace: bool,
+    all_backtraces: bool,
+    /// The default filter is only built when a backtrace is printed.
+    backtrace_filter: Option<BacktraceFilter>,
+    max_depth: Option<usize>,
+    marker: PhantomData<&'a T>,
+}
+
+impl<'a, T> DisplayChain<'a, T>
+    where T: ChainedError
+{
+    /// Creates a report for `error` with the default options.
+    pub fn new(error: &'a T) -> DisplayChain<'a, T> {
+        DisplayChain::from_error(error)
+    }
+
+    /// Creates a report for any `error`, whose causes may be errors of type
+    /// `T` or from its `links`.
+    fn from_error(error: &'a (error::Error + Send + 'static)) -> DisplayChain<'a, T> {
+        DisplayChain {
+            error: error,
+            indent: 0,
+            backtrace: true,
+            all_backtraces: false,
+            backtrace_filter: None,
+            max_depth: None,
+            marker: PhantomData,
+        }
+    }
+
+    /// Indents each cause by `n` more spaces than the previous one.
+    pub fn indent(mut self, n: usize) -> DisplayChain<'a, T> {
+        self.indent = n;
+        self
+    }
+
+    /// Whether to print the backtrace, if one was generated.
+    pub fn backtrace(mut self, backtrace: bool) -> DisplayChain<'a, T> {
+        self.backtrace = backtrace;
+        self
+    }
+
+    /// Whether to print the backtraces recorded for each error of the chain
+    /// with `set_link_backtraces`, and not only the backtrace of the
+    /// earliest error.
+    pub fn all_backtraces(mut self, all: bool) -> DisplayChain<'a, T> {
+        self.all_backtraces = all;
+        self
+    }
+
+    /// Only prints the frames of the backtrace allowed by `filter`. By
+    /// default, the frames from error-chain and the standard library are
+    /// hidden.
+    pub fn backtrace_filter(mut self, filter: BacktraceFilter) -> DisplayChain<'a, T> {
+        self.backtrace_filter = Some(filter);
+        self
+    }
+
+    /// Only prints the first `depth` causes. The remaining causes are
+    /// summarized in a single line.
+    pub fn max_depth(mut self, depth: usize) -> DisplayChain<'a, T> {
+        self.max_depth = Some(depth);
+        self
+    }
+}
+
+impl<'a, T> fmt::Display for DisplayChain<'a, T>
+    where T: ChainedError
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        try!(self.fmt_links(fmt, Links::new(self.error), "Error", 0, 0, 0));
+
+        if self.backtrace {
+            let mut labeled = false;
+            if self.all_backtraces {
+                for (e, state) in Links::<T>::new(self.error) {
+                    if let Some(backtrace) = state.and_then(|state| state.link_backtrace()) {
+                        try!(writeln!(fmt, "Backtrace of \"{}\":", e));
+                        try!(self.fmt_backtrace(fmt, backtrace));
+                        labeled = true;
+                    }
+                }
+            }
+            if let Some(backtrace) = T::extract_state(self.error).and_then(|s| s.backtrace()) {
+                if labeled {
+                    try!(writeln!(fmt, "Backtrace of the earliest error:"));
+                }
+                try!(self.fmt_backtrace(fmt, backtrace));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a, T> DisplayChain<'a, T>
+    where T: ChainedError
+{
+    /// Prints `backtrace` with the frames allowed by the filter.
+    fn fmt_backtrace(&self, fmt: &mut fmt::Formatter, backtrace: &Backtrace) -> fmt::Result {
+        match self.backtrace_filter {
+            Some(ref filter) => write!(fmt, "{}", filter.display(backtrace)),
+            None => write!(fmt, "{}", BacktraceFilter::default().display(backtrace)),
+        }
+    }
+
+    /// Prints the chain of `links`, the first error with the `first` label
+    /// and `margin` spaces of indentation, and its causes with `nested` more
+    /// spaces. The first error is at depth `start` in the whole chain.
+    fn fmt_links(&self,
+                 fmt: &mut fmt::Formatter,
+                 mut links: Links<T>,
+                 first: &str,
+                 margin: usize,
+                 nested: usize,
+                 start: usize)
+                 -> fmt::Result {
+        let mut depth = 0;
+        while let Some((e, state)) = links.next() {
+            let indent = if depth == 0 {
+                margin
+            } else {
+                margin + nested + depth * self.indent
+            };
+            if self.max_depth.map_or(false, |max| start + depth > max) {
+                try!(writeln!(fmt,
+                              "{:indent$}... and {} more",
+                              "",
+                              links.count() + 1,
+                              indent = indent));
+                break;
+            }
+            if let Some(errors) = aggregated_errors(e) {
+                let count = errors.len();
+                for (i, error) in errors.into_iter().enumerate() {
+                    let first = format!("Caused by ({}/{})", i + 1, count);
+                    try!(self.fmt_links(fmt, Links::new(error), &first, indent, 4, start + depth));
+                }
+                break;
+            }
+            if depth == 0 {
+                try!(writeln!(fmt, "{:indent$}{}: {}", "", first, e, indent = indent));
+            } else {
+                try!(writeln!(fmt, "{:indent$}Caused by: {}", "", e, indent = indent));
+            }
+            if let Some(location) = state.and_then(|state| state.location()) {
+                try!(writeln!(fmt, "{:indent$}    at {}", "", location, indent = indent));
+            }
+            for &(key, ref value) in state.map_or(&[][..], |state| state.context()) {
+                try!(writeln!(fmt, "{:indent$}    {}: {}", "", key, value, indent = indent));
+            }
+            depth += 1;
+        }
+        Ok(())
+    }
+}
+
+/// A location in the source code, as recorded by `bail!` and `chain_err!`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    /// The file, as returned by `file!()`.
+    pub file: &'static str,
+    /// The line, as returned by `line!()`.
+    pub line: u32,
+    /// The column, as returned by `column!()`.
+    pub column: u32,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// Wraps a string literal given to `chain_err!`, so that it is stored in the
+/// error without being copied.
+#[doc(hidden)]
+pub fn static_msg(s: &'static str) -> Cow<'static, str> {
+    Cow::Borrowed(s)
+}
+
+/// Records `location` in the state of `e`, unless a location is already
+/// known. This is used by `bail!` and `chain_err!`.
+#[doc(hidden)]
+pub fn with_location<CE: ChainedError>(mut e: CE, location: Location) -> CE {
+    {
+        let state = e.state_mut();
+        if state.location.is_none() {
+            state.location = Some(location);
+        }
+    }
+    e
+}
+
+/// Lets `bail!` record its location only in the errors generated by
+/// `error_chain!`, while still returning any other error type.
+///
+/// `(&e).location_kind()` resolves to `ChainedKind` when the type of `e`
+/// implements `ChainedError`, and to `OtherKind` through autoref otherwise.
+#[doc(hidden)]
+pub mod bail_location {
+    use {ChainedError, Location};
+
+    /// The error was generated by `error_chain!`.
+    pub struct Chained;
+
+    impl Chained {
+        /// Records `location` in `e`.
+        pub fn with_location<CE: ChainedError>(self, e: CE, location: Location) -> CE {
+            ::with_location(e, location)
+        }
+    }
+
+    /// The error has any other type.
+    pub struct Other;
+
+    impl Other {
+        /// Returns `e` unchanged.
+        pub fn with_location<E>(self, e: E, _: Location) -> E {
+            e
+        }
+    }
+
+    /// Selects `Chained` for the generated errors.
+    pub trait ChainedKind {
+        /// Returns how to record the location.
+        fn location_kind(&self) -> Chained {
+            Chained
+        }
+    }
+
+    impl<CE: ChainedError> ChainedKind for CE {}
+
+    /// Selects `Other` for all the other types.
+    pub trait OtherKind {
+        /// Returns how to record the location.
+        fn location_kind(&self) -> Other {
+            Other
+        }
+    }
+
+    impl<'a, E> OtherKind for &'a E {}
+}
+
+/// Common state between errors.
+///
+/// The errors of the chain are stored as `C`, which is chosen by the
+/// options of `error_chain!`.
+#[derive(Debug, Clone)]
+#[doc(hidden)]
+pub struct State<C = SendBox> {
+    /// Next error in the error chain.
+    pub next_error: Option<C>,
+    /// Key/value pairs describing the circumstances of the current error.
+    pub context: Vec<(&'static str, String)>,
+    /// Where the current error was created, if known.
+    pub location: Option<Location>,
+    /// Backtrace for the current error.
+    #[cfg(feature = "backtrace")]
+    pub backtrace: Option<Arc<InternalBacktrace>>,
+    /// Backtrace of the site where the current error was chained, if link
+    /// backtraces are enabled and `backtrace` comes from an earlier error.
+    #[cfg(feature = "backtrace")]
+    pub link_backtrace: Option<Arc<InternalBacktrace>>,
+}
+
+impl<C> Default for State<C> {
+    fn default() -> State<C> {
+        #[cfg(feature = "backtrace")]
+        let state = State {
+            next_error: None,
+            context: Vec::new(),
+            location: None,
+            backtrace: make_backtrace(),
+            link_backtrace: None,
+        };
+        #[cfg(not(feature = "backtrace"))]
+        let state = State {
+            next_error: None,
+            context: Vec::new(),
+            location: None,
+        };
+        state
+    }
+}
+
+impl<C: ErrorBox> State<C> {
+    /// Creates a new State type
+    pub fn new<CE: ChainedError>(e: C) -> State<C> {
+        #[cfg(feature = "backtrace")]
+        let state = {
+            let (backtrace, link_backtrace) = match CE::extract_backtrace(e.as_error()) {
+                Some(backtrace) if link_backtraces() => (Some(backtrace), make_backtrace()),
+                Some(backtrace) => (Some(backtrace), None),
+                None => (make_backtrace(), None),
+            };
+            State {
+                next_error: Some(e),
+                context: Vec::new(),
+                location: None,
+                backtrace: backtrace,
+                link_backtrace: link_backtrace,
+            }
+        };
+        #[cfg(not(feature = "backtrace"))]
+        let state = State {
+            next_error: Some(e),
+            context: Vec::new(),
+            location: None,
+        };
+
+        state
+    }
+
+    /// Converts the state of a linked error into a state for the error it
+    /// is linked into.
+    pub fn convert<D>(self) -> State<D>
+        where C: ConvertErrorBox<D>
+    {
+        #[cfg(feature = "backtrace")]
+        let state = State {
+            next_error: self.next_error.map(ConvertErrorBox::convert),
+            context: self.context,
+            location: self.location,
+            backtrace: self.backtrace,
+            link_backtrace: self.link_backtrace,
+        };
+        #[cfg(not(feature = "backtrace"))]
+        let state = State {
+            next_error: self.next_error.map(ConvertErrorBox::convert),
+            context: self.context,
+            location: self.location,
+        };
+        state
+    }
+
+    /// Appends key/value pairs to the context, formatting the values.
+    pub fn add_context(&mut self, context: &[(&'static str, &fmt::Display)]) {
+        self.context.extend(context.iter().map(|&(key, value)| (key, value.to_string())));
+    }
+
+    /// Returns the value of the first context entry for `key`.
+    pub fn context_value(&self, key: &str) -> Option<&str> {
+        self.context.iter().find(|&&(k, _)| k == key).map(|&(_, ref v)| &v[..])
+    }
+
+    /// Returns the inner backtrace if present.
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        #[cfg(feature = "backtrace")]
+        let b = self.backtrace.as_ref().map(|v| v.as_backtrace());
+        #[cfg(not(feature = "backtrace"))]
+        let b = None;
+        b
+    }
+
+    /// Returns the backtrace of the site where the error was chained, if
+    /// present.
+    pub fn link_backtrace(&self) -> Option<&Backtrace> {
+        #[cfg(feature = "backtrace")]
+        let b = self.link_backtrace.as_ref().map(|v| v.as_backtrace());
         #[cfg(not(feature = "backtrace"))]
         let b = None;
         b
     }
 }
 
+/// The parts of a `State` which don't depend on how the errors of the chain
+/// are stored, so that the states of all the errors of a chain can be read
+/// the same way.
+#[doc(hidden)]
+pub trait AnyState {
+    /// Returns the next error of the chain.
+    fn next_error(&self) -> Option<&(error::Error + Send + 'static)>;
+
+    /// Returns the key/value context.
+    fn context(&self) -> &[(&'static str, String)];
+
+    /// Returns the location in the source code, if known.
+    fn location(&self) -> Option<Location>;
+
+    /// Returns the backtrace if present.
+    fn backtrace(&self) -> Option<&Backtrace>;
+
+    /// Returns the backtrace of the site where the error was chained, if
+    /// present.
+    fn link_backtrace(&self) -> Option<&Backtrace>;
+
+    /// Returns the backtrace, to be shared with a new error of the chain.
+    #[cfg(feature = "backtrace")]
+    fn shared_backtrace(&self) -> Option<&Arc<InternalBacktrace>>;
+}
+
+impl<C: ErrorBox> AnyState for State<C> {
+    fn next_error(&self) -> Option<&(error::Error + Send + 'static)> {
+        self.next_error.as_ref().map(ErrorBox::as_error)
+    }
+
+    fn context(&self) -> &[(&'static str, String)] {
+        &self.context
+    }
+
+    fn location(&self) -> Option<Location> {
+        self.location
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        State::backtrace(self)
+    }
+
+    fn link_backtrace(&self) -> Option<&Backtrace> {
+        State::link_backtrace(self)
+    }
+
+    #[cfg(feature = "backtrace")]
+    fn shared_backtrace(&self) -> Option<&Arc<InternalBacktrace>> {
+        self.backtrace.as_ref()
+    }
+}
+
+/// An error from `links` which was converted with the `chained` mode.
+///
+/// The kind of the linked error is moved into the new error, so the linked
+/// error is kept in the chain as a `LinkedError`, which has the same
+/// message and the same causes.
+#[derive(Debug)]
+pub struct LinkedError<C = SendBox> {
+    message: String,
+    description: String,
+    state: State<C>,
+}
+
+impl<C: ErrorBox> LinkedError<C> {
+    /// Creates a `LinkedError` from the rendered kind and the state of the
+    /// linked error. This is used in the generated error implementations.
+    #[doc(hidden)]
+    pub fn new(message: String, description: String, state: State<C>) -> LinkedError<C> {
+        LinkedError {
+            message: message,
+            description: description,
+            state: state,
+        }
+    }
+
+    /// Returns the state of the linked error.
+    #[doc(hidden)]
+    pub fn state(&self) -> &State<C> {
+        &self.state
+    }
+
+    /// Returns the key/value context attached to the linked error.
+    pub fn context(&self) -> &[(&'static str, String)] {
+        &self.state.context
+    }
+
+    /// Returns the location in the source code where the linked error was
+    /// created, if known.
+    pub fn location(&self) -> Option<Location> {
+        self.state.location
+    }
+}
+
+impl<C> fmt::Display for LinkedError<C> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(&self.message)
+    }
+}
+
+impl<C: ErrorBox> error::Error for LinkedError<C> {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        self.state.next_error.as_ref().map(|c| c.as_error() as &error::Error)
+    }
+
+    fn source(&self) -> Option<&(error::Error + 'static)> {
+        self.state.next_error.as_ref().map(|c| c.as_error() as &(error::Error + 'static))
+    }
+}
+
+/// Returns the state of `e` if it is a `LinkedError`, whatever the way the
+/// errors of its chain are stored.
+#[doc(hidden)]
+pub fn linked_state<'a>(e: &'a (error::Error + 'static)) -> Option<&'a AnyState> {
+    if let Some(e) = e.downcast_ref::<LinkedError<SendBox>>() {
+        return Some(e.state());
+    }
+    if let Some(e) = e.downcast_ref::<LinkedError<SyncBox>>() {
+        return Some(e.state());
+    }
+    if let Some(e) = e.downcast_ref::<LinkedError<SharedBox>>() {
+        return Some(e.state());
+    }
+    None
+}
+
+/// Several independent errors, reported together.
+///
+/// This is the cause of the errors created with the generated
+/// `Error::aggregate` and `Error::collect_all`. As the errors are not
+/// chained to each other, `source()` returns `None`: they are accessed
+/// with `iter`, and `display_chain` prints them as a tree.
+#[derive(Debug)]
+pub struct AggregateError<C = SendBox> {
+    errors: Vec<C>,
+}
+
+impl<C: ErrorBox> AggregateError<C> {
+    /// Creates an `AggregateError` from boxed errors. This is used in the
+    /// generated error implementations.
+    #[doc(hidden)]
+    pub fn new(errors: Vec<C>) -> AggregateError<C> {
+        AggregateError { errors: errors }
+    }
+
+    /// Returns the number of errors.
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// Returns whether there are no errors.
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Iterates over the errors.
+    pub fn iter(&self) -> AggregateIter<C> {
+        AggregateIter(self.errors.iter())
+    }
+}
+
+impl<C> fmt::Display for AggregateError<C> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{} errors occurred", self.errors.len())
+    }
+}
+
+impl<C: ErrorBox> error::Error for AggregateError<C> {
+    fn description(&self) -> &str {
+        "multiple errors occurred"
+    }
+}
+
+/// Iterator over the errors of an `AggregateError`.
+pub struct AggregateIter<'a, C: 'a = SendBox>(slice::Iter<'a, C>);
+
+impl<'a, C: ErrorBox> Iterator for AggregateIter<'a, C> {
+    type Item = &'a (error::Error + 'static);
+
+    fn next(&mut self) -> Option<&'a (error::Error + 'static)> {
+        self.0.next().map(|e| e.as_error() as &(error::Error + 'static))
+    }
+}
+
+/// Returns the errors of `e` if it is an `AggregateError`, whatever the way
+/// they are stored.
+fn aggregated_errors<'a>(e: &'a (error::Error + 'static))
+                         -> Option<Vec<&'a (error::Error + Send + 'static)>> {
+    if let Some(e) = e.downcast_ref::<AggregateError<SendBox>>() {
+        return Some(e.errors.iter().map(ErrorBox::as_error).collect());
+    }
+    if let Some(e) = e.downcast_ref::<AggregateError<SyncBox>>() {
+        return Some(e.errors.iter().map(ErrorBox::as_error).collect());
+    }
+    if let Some(e) = e.downcast_ref::<AggregateError<SharedBox>>() {
+        return Some(e.errors.iter().map(ErrorBox::as_error).collect());
+    }
+    None
+}
+
 /// Exits a function early with an error
 ///
 /// The `bail!` macro provides an easy way to exit a function.
@@ -493,6 +1915,10 @@ impl State {
 /// # }
 /// ```
 ///
+/// except that it also records the location of the `bail!` in the error,
+/// which can be read back with `Error::location()`, when the error is
+/// generated by `error_chain!`.
+///
 /// And as shorthand it takes a formatting string a la `println!`:
 ///
 /// ```
@@ -546,14 +1972,292 @@ impl State {
 /// ```
 #[macro_export]
 macro_rules! bail {
-    ($e:expr) => {
-        return Err($e.into());
-    };
+    ($e:expr) => {{
+        let e = $e.into();
+        // Gives `e` the error type of the function before it is inspected.
+        if false {
+            return Err(e);
+        }
+        #[allow(unused_imports)]
+        use $crate::bail_location::{ChainedKind, OtherKind};
+        let kind = (&e).location_kind();
+        return Err(kind.with_location(e,
+                                      $crate::Location {
+                                          file: file!(),
+                                          line: line!(),
+                                          column: column!(),
+                                      }));
+    }};
     ($fmt:expr, $($arg:tt)+) => {
-        return Err(format!($fmt, $($arg)+).into());
+        $crate::bail!(format!($fmt, $($arg)+))
     };
 }
 
+/// Extends the error chain and records the location of the call
+///
+/// `chain_err!(result, expr)` is equivalent to
+/// `result.chain_err(|| expr)`, except that it also records the location
+/// of the `chain_err!` in the new error, which can be read back with
+/// `Error::location()`. Like `bail!`, it also accepts a formatting string
+/// a la `println!`, which is only formatted if `result` is an `Err`.
+///
+/// The `ResultExt` trait generated by `error_chain!` must be in scope.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate error_chain;
+/// # fn main() {}
+/// error_chain! {
+///     foreign_links {
+///         Io(::std::io::Error);
+///     }
+/// }
+///
+/// fn open(path: &str) -> Result<::std::fs::File> {
+///     let file = chain_err!(::std::fs::File::open(path), "unable to open {}", path)?;
+///     Ok(file)
+/// }
+/// ```
+#[macro_export]
+macro_rules! chain_err {
+    ($r:expr, $e:literal) => {
+        $crate::chain_err!($r, $crate::static_msg($e))
+    };
+    ($r:expr, $e:expr) => {
+        $r.chain_err(|| $e).map_err(|e| {
+            $crate::with_location(e,
+                                  $crate::Location {
+                                      file: file!(),
+                                      line: line!(),
+                                      column: column!(),
+                                  })
+        })
+    };
+    ($r:expr, $fmt:expr, $($arg:tt)+) => {
+        $r.chain_err(|| format!($fmt, $($arg)+)).map_err(|e| {
+            $crate::with_location(e,
+                                  $crate::Location {
+                                      file: file!(),
+                                      line: line!(),
+                                      column: column!(),
+                                  })
+        })
+    };
+}
+
+/// Exits a function early with an error if the condition is not satisfied
+///
+/// The `ensure!` macro is a convenience helper that provides a way to exit
+/// a function with an error if the given condition fails.
+///
+/// As an example, `ensure!(condition, "error code: {}", errcode)` is
+/// equivalent to
+///
+/// ```
+/// # #[macro_use] extern crate error_chain;
+/// # error_chain! { }
+/// # fn main() { }
+/// # fn foo() -> Result<()> {
+/// # let condition = true;
+/// # let errcode = 0u8;
+/// if !condition {
+///     bail!("error code: {}", errcode);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// See documentation for `bail!` macro for further details.
+///
+/// # Examples
+///
+/// Ensuring a condition with a custom error:
+///
+/// ```
+/// # #[macro_use] extern crate error_chain;
+/// # fn main() {}
+/// error_chain! {
+///     errors { NotPositive(n: i32) }
+/// }
+///
+/// fn foo(n: i32) -> Result<()> {
+///     ensure!(n > 0, ErrorKind::NotPositive(n));
+///
+///     Ok(())
+/// }
+/// ```
+#[macro_export]
+macro_rules! ensure {
+    ($cond:expr, $e:expr) => {
+        if !($cond) {
+            $crate::bail!($e);
+        }
+    };
+    ($cond:expr, $fmt:expr, $($arg:tt)+) => {
+        if !($cond) {
+            $crate::bail!($fmt, $($arg)+);
+        }
+    };
+}
+
+/// Asserts that a `Result` is an error of the given kind
+///
+/// `assert_err_kind!(result, pattern)` panics unless `result` is an `Err`
+/// whose kind matches `pattern`. The message of the panic contains the full
+/// error chain.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate error_chain;
+/// error_chain! {
+///     errors {
+///         NotFound(name: String)
+///     }
+/// }
+///
+/// fn find(name: &str) -> Result<()> {
+///     bail!(ErrorKind::NotFound(name.to_string()))
+/// }
+///
+/// # fn main() {
+/// assert_err_kind!(find("foo"), ErrorKind::NotFound(..));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! assert_err_kind {
+    ($result:expr, $kind:pat) => {
+        match $result {
+            Err(ref e) => {
+                match *$crate::ChainedError::kind(e) {
+                    $kind => {}
+                    _ => {
+                        panic!("assertion failed: expected an error of kind `{}`, got:\n{}",
+                               stringify!($kind),
+                               $crate::ChainedError::display_chain(e).backtrace(false))
+                    }
+                }
+            }
+            Ok(_) => {
+                panic!("assertion failed: expected an error of kind `{}`, got `Ok`",
+                       stringify!($kind))
+            }
+        }
+    };
+}
+
+/// Asserts that the errors of a chain have the given types and messages
+///
+/// `assert_chain!(error, [Type: "message", ...])` panics unless the chain
+/// of `error`, as returned by `iter()`, has exactly one error for each
+/// entry, of type `Type` and displayed as `"message"`. The message of the
+/// panic contains the full error chain.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate error_chain;
+/// error_chain! {}
+///
+/// # fn main() {
+/// let inner: Result<()> = Err("inner".into());
+/// let err = inner.chain_err(|| "outer").unwrap_err();
+/// assert_chain!(err, [Error: "outer", Error: "inner"]);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! assert_chain {
+    ($error:expr, [ $( $typ:ty: $message:expr ),* $(,)* ]) => {{
+        let error = &$error;
+        let chain: Vec<_> = $crate::ChainedError::iter(error).collect();
+        let mut i = 0;
+        $(
+            match chain.get(i) {
+                Some(e) if e.is::<$typ>() && e.to_string() == $message => {}
+                _ => {
+                    panic!("assertion failed: expected error {} of the chain to be a `{}` \
+                            displayed as {:?}, got:\n{}",
+                           i,
+                           stringify!($typ),
+                           $message,
+                           $crate::ChainedError::display_chain(error).backtrace(false))
+                }
+            }
+            i += 1;
+        )*
+        if chain.len() != i {
+            panic!("assertion failed: expected {} errors in the chain, got {}:\n{}",
+                   i,
+                   chain.len(),
+                   $crate::ChainedError::display_chain(error).backtrace(false))
+        }
+    }};
+}
+
+/// Logs `e`, with all its causes and the backtrace, if any, as a single
+/// record. This is used in the generated error implementations.
+#[cfg(feature = "log")]
+#[doc(hidden)]
+pub fn log_error<CE: ChainedError>(e: &(error::Error + Send + 'static),
+                                   level: log::Level,
+                                   target: &str) {
+    if log_enabled!(target: target, level) {
+        let report = DisplayChain::<CE>::from_error(e).to_string();
+        log!(target: target, level, "{}", report.trim_end());
+    }
+}
+
+/// Creates a `main` function which calls a function returning a `Result`
+///
+/// `quick_main!(run)` expands to a `main` function which calls `run()`.
+/// If it returns an error, the full chain and the backtrace are printed to
+/// stderr, as with `display_chain`, and the process exits with the exit
+/// code of the error kind. The exit code of a kind from `errors` is given
+/// with the `exit_code()` clause, kinds from `links` have the exit code of
+/// the linked kind, and all other kinds exit with 1.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate error_chain;
+/// error_chain! {
+///     errors {
+///         InvalidConfig {
+///             description("invalid configuration")
+///             exit_code(78)
+///         }
+///     }
+/// }
+///
+/// quick_main!(run);
+///
+/// fn run() -> Result<()> {
+///     Ok(())
+/// }
+/// ```
+#[macro_export]
+macro_rules! quick_main {
+    ($main:expr) => {
+        fn main() {
+            ::std::process::exit($crate::report_main($main()));
+        }
+    };
+}
+
+/// Prints the error of `result`, if any, to stderr and returns the exit
+/// code of the program. This is used by `quick_main!`.
+#[doc(hidden)]
+pub fn report_main<CE: ChainedError>(result: Result<(), CE>) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(ref e) => {
+            let _ = write!(io::stderr(), "{}", e.display_chain());
+            e.exit_code()
+        }
+    }
+}
+
 #[doc(hidden)]
 pub mod mock {
     error_chain!{}
@@ -2,25 +2,37 @@
 // Changes:
 //   - replace `impl Error` by `impl Item::description`
 //   - $imeta
+//   - add `name()`, `code()` and `exit_code()` to the generated enum
+//   - add `contained_error()` to the generated enum, from `cause()`
+//   - `ERROR $error;` prefix, to also implement `From` for the error type
+//     with the `from()` clauses
 
 #[macro_export]
 macro_rules! quick_error {
     (   $(#[$meta:meta])*
         pub enum $name:ident { $($chunks:tt)* }
     ) => {
-        quick_error!(SORT [pub enum $name $(#[$meta])* ]
+        quick_error!(SORT [pub enum $name $(#[$meta])* ; ]
             items [] buf []
             queue [ $($chunks)* ]);
     };
     (   $(#[$meta:meta])*
         enum $name:ident { $($chunks:tt)* }
     ) => {
-        quick_error!(SORT [enum $name $(#[$meta])* ]
+        quick_error!(SORT [enum $name $(#[$meta])* ; ]
+            items [] buf []
+            queue [ $($chunks)* ]);
+    };
+    (   ERROR $error:ident;
+        $(#[$meta:meta])*
+        pub enum $name:ident { $($chunks:tt)* }
+    ) => {
+        quick_error!(SORT [pub enum $name $(#[$meta])* ; $error ]
             items [] buf []
             queue [ $($chunks)* ]);
     };
     // Queue is empty, can do the work
-    (SORT [enum $name:ident $( #[$meta:meta] )*]
+    (SORT [enum $name:ident $( #[$meta:meta] )* ; $( $error:ident )*]
         items [$($( #[$imeta:meta] )*
                   => $iitem:ident: $imode:tt [$( $ivar:ident: $ityp:ty ),*]
                                 {$( $ifuncs:tt )*} )* ]
@@ -32,14 +44,14 @@ macro_rules! quick_error {
             queue [$($( #[$imeta] )*
                       => $iitem: $imode [$( $ivar: $ityp ),*] )*]
         );
-        quick_error!(IMPLEMENTATIONS $name {$(
+        quick_error!(IMPLEMENTATIONS $name [$( $error )*] {$(
            $iitem: $imode [$(#[$imeta])*] [$( $ivar: $ityp ),*] {$( $ifuncs )*}
            )*});
         $(
             quick_error!(ERROR_CHECK $imode $($ifuncs)*);
         )*
     };
-    (SORT [pub enum $name:ident $( #[$meta:meta] )*]
+    (SORT [pub enum $name:ident $( #[$meta:meta] )* ; $( $error:ident )*]
         items [$($( #[$imeta:meta] )*
                   => $iitem:ident: $imode:tt [$( $ivar:ident: $ityp:ty ),*]
                                 {$( $ifuncs:tt )*} )* ]
@@ -51,7 +63,7 @@ macro_rules! quick_error {
             queue [$($( #[$imeta] )*
                       => $iitem: $imode [$( $ivar: $ityp ),*] )*]
         );
-        quick_error!(IMPLEMENTATIONS $name {$(
+        quick_error!(IMPLEMENTATIONS $name [$( $error )*] {$(
            $iitem: $imode [$(#[$imeta])*] [$( $ivar: $ityp ),*] {$( $ifuncs )*}
            )*});
         $(
@@ -256,7 +268,7 @@ macro_rules! quick_error {
         );
     };
     (IMPLEMENTATIONS
-        $name:ident {$(
+        $name:ident $errors:tt {$(
             $item:ident: $imode:tt [$(#[$imeta:meta])*] [$( $var:ident: $typ:ty ),*] {$( $funcs:tt )*}
         )*}
     ) => {
@@ -327,10 +339,71 @@ macro_rules! quick_error {
                     )*
                 }
             }
+
+            /// The name of the variant.
+            pub fn name(&self) -> &'static str {
+                match *self {
+                    $(
+                        $(#[$imeta])*
+                        quick_error!(ITEM_PATTERN
+                            $name $item: $imode [$( ref $var ),*]
+                        ) => stringify!($item),
+                    )*
+                }
+            }
+
+            /// The stable code of the error kind, if it was given one.
+            pub fn code(&self) -> Option<&'static str> {
+                match *self {
+                    $(
+                        $(#[$imeta])*
+                        quick_error!(ITEM_PATTERN
+                            $name $item: $imode [$( ref $var ),*]
+                        ) => {
+                            quick_error!(FIND_CODE_IMPL
+                                $item: $imode
+                                {$( $funcs )*})
+                        }
+                    )*
+                }
+            }
+
+            /// The error contained in this error kind, as declared with the
+            /// `cause()` clause.
+            pub fn contained_error(&self) -> Option<&(::std::error::Error + 'static)> {
+                match *self {
+                    $(
+                        $(#[$imeta])*
+                        quick_error!(ITEM_PATTERN
+                            $name $item: $imode [$( ref $var ),*]
+                        ) => {
+                            quick_error!(FIND_CAUSE_IMPL
+                                $item: $imode [$( $var ),*]
+                                {$( $funcs )*})
+                        }
+                    )*
+                }
+            }
+
+            /// The exit code of a program which fails with this error kind.
+            pub fn exit_code(&self) -> i32 {
+                match *self {
+                    $(
+                        $(#[$imeta])*
+                        quick_error!(ITEM_PATTERN
+                            $name $item: $imode [$( ref $var ),*]
+                        ) => {
+                            quick_error!(FIND_EXIT_CODE_IMPL
+                                $item: $imode
+                                {$( $funcs )*})
+                        }
+                    )*
+                }
+            }
         }
         $(
             quick_error!(FIND_FROM_IMPL
-                $name $item: $imode [$( $var:$typ ),*]
+                $name $errors $item: $imode [$( $var:$typ ),*]
                 {$( $funcs )*});
         )*
     };
@@ -385,6 +458,40 @@ macro_rules! quick_error {
     ) => {
         stringify!($item)
     };
+    (FIND_CODE_IMPL $item:ident: $imode:tt
+        { code($expr:expr) $( $tail:tt )*}
+    ) => {
+        Some($expr)
+    };
+    (FIND_CODE_IMPL $item:ident: $imode:tt
+        { $t:tt $( $tail:tt )*}
+    ) => {
+        quick_error!(FIND_CODE_IMPL
+            $item: $imode
+            {$( $tail )*})
+    };
+    (FIND_CODE_IMPL $item:ident: $imode:tt
+        { }
+    ) => {
+        None
+    };
+    (FIND_EXIT_CODE_IMPL $item:ident: $imode:tt
+        { exit_code($expr:expr) $( $tail:tt )*}
+    ) => {
+        $expr
+    };
+    (FIND_EXIT_CODE_IMPL $item:ident: $imode:tt
+        { $t:tt $( $tail:tt )*}
+    ) => {
+        quick_error!(FIND_EXIT_CODE_IMPL
+            $item: $imode
+            {$( $tail )*})
+    };
+    (FIND_EXIT_CODE_IMPL $item:ident: $imode:tt
+        { }
+    ) => {
+        1
+    };
     (FIND_CAUSE_IMPL $item:ident: $imode:tt
         [$( $var:ident ),*]
         { cause($expr:expr) $( $tail:tt )*}
@@ -405,7 +512,7 @@ macro_rules! quick_error {
     ) => {
         None
     };
-    (FIND_FROM_IMPL $name:ident $item:ident: $imode:tt
+    (FIND_FROM_IMPL $name:ident $errors:tt $item:ident: $imode:tt
         [$( $var:ident: $typ:ty ),*]
         { from() $( $tail:tt )*}
     ) => {
@@ -415,12 +522,13 @@ macro_rules! quick_error {
                     $name::$item($var)
                 }
             }
+            quick_error!(FROM_ERROR_IMPL $errors $name $typ);
         )*
         quick_error!(FIND_FROM_IMPL
-            $name $item: $imode [$( $var:$typ ),*]
+            $name $errors $item: $imode [$( $var:$typ ),*]
             {$( $tail )*});
     };
-    (FIND_FROM_IMPL $name:ident $item:ident: UNIT
+    (FIND_FROM_IMPL $name:ident $errors:tt $item:ident: UNIT
         [ ]
         { from($ftyp:ty) $( $tail:tt )*}
     ) => {
@@ -429,11 +537,12 @@ macro_rules! quick_error {
                 $name::$item
             }
         }
+        quick_error!(FROM_ERROR_IMPL $errors $name $ftyp);
         quick_error!(FIND_FROM_IMPL
-            $name $item: UNIT [  ]
+            $name $errors $item: UNIT [  ]
             {$( $tail )*});
     };
-    (FIND_FROM_IMPL $name:ident $item:ident: TUPLE
+    (FIND_FROM_IMPL $name:ident $errors:tt $item:ident: TUPLE
         [$( $var:ident: $typ:ty ),*]
         { from($fvar:ident: $ftyp:ty) -> ($( $texpr:expr ),*) $( $tail:tt )*}
     ) => {
@@ -442,11 +551,12 @@ macro_rules! quick_error {
                 $name::$item($( $texpr ),*)
             }
         }
+        quick_error!(FROM_ERROR_IMPL $errors $name $ftyp);
         quick_error!(FIND_FROM_IMPL
-            $name $item: TUPLE [$( $var:$typ ),*]
+            $name $errors $item: TUPLE [$( $var:$typ ),*]
             { $($tail)* });
     };
-    (FIND_FROM_IMPL $name:ident $item:ident: STRUCT
+    (FIND_FROM_IMPL $name:ident $errors:tt $item:ident: STRUCT
         [$( $var:ident: $typ:ty ),*]
         { from($fvar:ident: $ftyp:ty) -> {$( $tvar:ident: $texpr:expr ),*} $( $tail:tt )*}
     ) => {
@@ -457,24 +567,33 @@ macro_rules! quick_error {
                 }
             }
         }
+        quick_error!(FROM_ERROR_IMPL $errors $name $ftyp);
         quick_error!(FIND_FROM_IMPL
-            $name $item: STRUCT [$( $var:$typ ),*]
+            $name $errors $item: STRUCT [$( $var:$typ ),*]
             { $($tail)* });
     };
-    (FIND_FROM_IMPL $name:ident $item:ident: $imode:tt
+    (FIND_FROM_IMPL $name:ident $errors:tt $item:ident: $imode:tt
         [$( $var:ident: $typ:ty ),*]
         { $t:tt $( $tail:tt )*}
     ) => {
         quick_error!(FIND_FROM_IMPL
-            $name $item: $imode [$( $var:$typ ),*]
+            $name $errors $item: $imode [$( $var:$typ ),*]
             {$( $tail )*}
         );
     };
-    (FIND_FROM_IMPL $name:ident $item:ident: $imode:tt
+    (FIND_FROM_IMPL $name:ident $errors:tt $item:ident: $imode:tt
         [$( $var:ident: $typ:ty ),*]
         { }
     ) => {
     };
+    (FROM_ERROR_IMPL [] $name:ident $typ:ty) => {};
+    (FROM_ERROR_IMPL [$error:ident] $name:ident $typ:ty) => {
+        impl From<$typ> for $error {
+            fn from(e: $typ) -> $error {
+                $error::from_kind($name::from(e))
+            }
+        }
+    };
     (ITEM_BODY $(#[$imeta:meta])* $item:ident: UNIT
     ) => { };
     (ITEM_BODY $(#[$imeta:meta])* $item:ident: TUPLE
@@ -513,6 +632,10 @@ macro_rules! quick_error {
     => { quick_error!(ERROR_CHECK $imode $($tail)*); };
     (ERROR_CHECK $imode:tt description($expr:expr) $( $tail:tt )*)
     => { quick_error!(ERROR_CHECK $imode $($tail)*); };
+    (ERROR_CHECK $imode:tt code($expr:expr) $( $tail:tt )*)
+    => { quick_error!(ERROR_CHECK $imode $($tail)*); };
+    (ERROR_CHECK $imode:tt exit_code($expr:expr) $( $tail:tt )*)
+    => { quick_error!(ERROR_CHECK $imode $($tail)*); };
     (ERROR_CHECK $imode:tt cause($expr:expr) $($tail:tt)*)
     => { quick_error!(ERROR_CHECK $imode $($tail)*); };
     (ERROR_CHECK $imode:tt from() $($tail:tt)*)
@@ -0,0 +1,208 @@
+//! Serializable snapshots of error chains, available with the `serde`
+//! feature.
+
+use std::error;
+use std::fmt;
+
+use {ChainedError, ErrorChainIter};
+
+/// A serializable snapshot of a full error chain.
+///
+/// The snapshot is created from any error generated by `error_chain!` with
+/// `ErrorSnapshot::new`. It records the kind, message and description of the
+/// error, the message and description of each of its causes, and the frames
+/// of its backtrace.
+///
+/// Once deserialized, possibly in another process, the snapshot is an opaque
+/// error which implements `std::error::Error` with the same chain as the
+/// original error.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorSnapshot {
+    kind: String,
+    message: String,
+    description: String,
+    #[serde(rename = "causes", with = "causes")]
+    cause: Option<Box<CauseSnapshot>>,
+    backtrace: Option<Vec<FrameSnapshot>>,
+}
+
+impl ErrorSnapshot {
+    /// Takes a snapshot of `e` and of all its causes.
+    pub fn new<CE: ChainedError>(e: &CE) -> ErrorSnapshot {
+        let causes = e.iter()
+            .skip(1)
+            .map(|cause| {
+                CauseSnapshot {
+                    message: cause.to_string(),
+                    description: cause.description().to_string(),
+                    cause: None,
+                }
+            })
+            .collect();
+        ErrorSnapshot {
+            kind: e.kind_name().to_string(),
+            message: e.to_string(),
+            description: e.description().to_string(),
+            cause: causes::nest(causes),
+            backtrace: frames(e),
+        }
+    }
+
+    /// Returns the name of the variant of the kind of the original error.
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    /// Returns the frames of the backtrace of the original error, if any.
+    pub fn backtrace(&self) -> Option<&[FrameSnapshot]> {
+        self.backtrace.as_ref().map(|frames| &frames[..])
+    }
+
+    /// Iterates over the error chain.
+    pub fn iter(&self) -> ErrorChainIter {
+        ErrorChainIter(Some(self))
+    }
+}
+
+impl fmt::Display for ErrorSnapshot {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(&self.message)
+    }
+}
+
+impl error::Error for ErrorSnapshot {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        self.cause.as_ref().map(|cause| &**cause as &error::Error)
+    }
+
+    fn source(&self) -> Option<&(error::Error + 'static)> {
+        self.cause.as_ref().map(|cause| &**cause as &(error::Error + 'static))
+    }
+}
+
+/// One of the causes of an `ErrorSnapshot`.
+#[derive(Debug, Clone, PartialEq)]
+struct CauseSnapshot {
+    message: String,
+    description: String,
+    cause: Option<Box<CauseSnapshot>>,
+}
+
+impl fmt::Display for CauseSnapshot {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(&self.message)
+    }
+}
+
+impl error::Error for CauseSnapshot {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        self.cause.as_ref().map(|cause| &**cause as &error::Error)
+    }
+
+    fn source(&self) -> Option<&(error::Error + 'static)> {
+        self.cause.as_ref().map(|cause| &**cause as &(error::Error + 'static))
+    }
+}
+
+/// A symbol of a backtrace frame, as recorded by `ErrorSnapshot`.
+///
+/// Frames with several symbols, because of inlining, are recorded as one
+/// `FrameSnapshot` per symbol, and frames which could not be resolved as a
+/// single `FrameSnapshot` without any information.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrameSnapshot {
+    /// The demangled name of the function.
+    pub name: Option<String>,
+    /// The source file of the function.
+    pub filename: Option<String>,
+    /// The line in the source file.
+    pub lineno: Option<u32>,
+}
+
+#[cfg(feature = "backtrace")]
+fn frames<CE: ChainedError>(e: &CE) -> Option<Vec<FrameSnapshot>> {
+    e.backtrace().map(|backtrace| {
+        let mut frames = Vec::new();
+        for frame in backtrace.frames() {
+            if frame.symbols().is_empty() {
+                frames.push(FrameSnapshot {
+                    name: None,
+                    filename: None,
+                    lineno: None,
+                });
+            }
+            for symbol in frame.symbols() {
+                frames.push(FrameSnapshot {
+                    name: symbol.name().map(|name| name.to_string()),
+                    filename: symbol.filename().map(|path| path.display().to_string()),
+                    lineno: symbol.lineno(),
+                });
+            }
+        }
+        frames
+    })
+}
+
+#[cfg(not(feature = "backtrace"))]
+fn frames<CE: ChainedError>(_: &CE) -> Option<Vec<FrameSnapshot>> {
+    None
+}
+
+/// The causes are nested in memory, so that each of them can implement
+/// `Error::cause`, but they are serialized as a flat list.
+mod causes {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::CauseSnapshot;
+
+    #[derive(Serialize, Deserialize)]
+    struct Cause {
+        message: String,
+        description: String,
+    }
+
+    pub fn nest(causes: Vec<CauseSnapshot>) -> Option<Box<CauseSnapshot>> {
+        causes.into_iter().rev().fold(None, |next, mut cause| {
+            cause.cause = next;
+            Some(Box::new(cause))
+        })
+    }
+
+    pub fn serialize<S>(cause: &Option<Box<CauseSnapshot>>, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let mut causes = Vec::new();
+        let mut next = cause.as_ref();
+        while let Some(cause) = next {
+            causes.push(Cause {
+                message: cause.message.clone(),
+                description: cause.description.clone(),
+            });
+            next = cause.cause.as_ref();
+        }
+        causes.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Box<CauseSnapshot>>, D::Error>
+        where D: Deserializer<'de>
+    {
+        let causes: Vec<Cause> = try!(Deserialize::deserialize(deserializer));
+        Ok(nest(causes.into_iter()
+            .map(|cause| {
+                CauseSnapshot {
+                    message: cause.message,
+                    description: cause.description,
+                    cause: None,
+                }
+            })
+            .collect()))
+    }
+}
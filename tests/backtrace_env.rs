@@ -0,0 +1,34 @@
+// The `RUST_BACKTRACE` environment variable is only read once per process,
+// so this test has its own test binary.
+
+#![cfg(feature = "backtrace")]
+
+#[macro_use]
+extern crate error_chain;
+
+#[test]
+fn has_backtrace_depending_on_env() {
+    use std::env;
+    use error_chain::BacktracePolicy;
+
+    error_chain! {
+        errors {
+            MyError
+        }
+    }
+
+    assert_eq!(error_chain::backtrace_policy(), BacktracePolicy::Env);
+
+    // RUST_BACKTRACE set to anything but 0
+    env::set_var("RUST_BACKTRACE", "yes");
+    let err = Error::from(ErrorKind::MyError);
+    assert!(err.backtrace().is_some());
+
+    // The variable was read when the first error was created.
+    env::set_var("RUST_BACKTRACE", "0");
+    let err = Error::from(ErrorKind::MyError);
+    assert!(err.backtrace().is_some());
+    env::remove_var("RUST_BACKTRACE");
+    let err = Error::from(ErrorKind::MyError);
+    assert!(err.backtrace().is_some());
+}
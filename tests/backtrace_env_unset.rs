@@ -0,0 +1,31 @@
+// The `RUST_BACKTRACE` environment variable is only read once per process,
+// so this test has its own test binary.
+
+#![cfg(feature = "backtrace")]
+
+#[macro_use]
+extern crate error_chain;
+
+#[test]
+fn no_backtrace_without_env() {
+    use std::env;
+    use error_chain::BacktracePolicy;
+
+    error_chain! {
+        errors {
+            MyError
+        }
+    }
+
+    assert_eq!(error_chain::backtrace_policy(), BacktracePolicy::Env);
+
+    // RUST_BACKTRACE unset
+    env::remove_var("RUST_BACKTRACE");
+    let err = Error::from(ErrorKind::MyError);
+    assert!(err.backtrace().is_none());
+
+    // The variable was read when the first error was created.
+    env::set_var("RUST_BACKTRACE", "1");
+    let err = Error::from(ErrorKind::MyError);
+    assert!(err.backtrace().is_none());
+}
@@ -0,0 +1,37 @@
+// The backtrace policy is global, so this test has its own test binary.
+
+#![cfg(feature = "backtrace")]
+
+#[macro_use]
+extern crate error_chain;
+
+#[test]
+fn filtered_backtrace() {
+    use error_chain::{BacktraceFilter, BacktracePolicy};
+
+    error_chain! {}
+
+    #[inline(never)]
+    fn fails() -> Result<()> {
+        bail!("fails")
+    }
+
+    error_chain::set_backtrace_policy(BacktracePolicy::Always);
+    let err = fails().unwrap_err();
+    let backtrace = err.backtrace().unwrap();
+
+    // Everything from the creation of the error is hidden.
+    let filtered = BacktraceFilter::default().display(backtrace).to_string();
+    let mut lines = filtered.lines();
+    assert_eq!(lines.next(), Some("stack backtrace:"));
+    let first = lines.next().unwrap();
+    assert!(first.contains("backtrace_filter::filtered_backtrace::fails"),
+            "unexpected first frame: {}",
+            filtered);
+
+    let full = BacktraceFilter::empty().display(backtrace).to_string();
+    assert!(full.contains("from_kind"));
+
+    let report = err.display_chain().to_string();
+    assert!(report.contains(&filtered));
+}
@@ -0,0 +1,17 @@
+// The macros are called by path, without `#[macro_use]`, so they must not
+// rely on the other macros of error-chain being imported.
+
+extern crate error_chain;
+
+#[test]
+fn ensure_by_path() {
+    fn check(x: u32) -> Result<(), String> {
+        error_chain::ensure!(x != 0, "x is zero");
+        error_chain::ensure!(x < 10, "x is too big: {}", x);
+        Ok(())
+    }
+
+    assert!(check(1).is_ok());
+    assert_eq!(check(0).unwrap_err(), "x is zero");
+    assert_eq!(check(10).unwrap_err(), "x is too big: 10");
+}
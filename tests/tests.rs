@@ -5,6 +5,10 @@
 
 #[macro_use]
 extern crate error_chain;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+#[cfg(feature = "log")]
+extern crate log;
 
 #[test]
 fn smoke_test_1() {
@@ -202,8 +206,8 @@ fn empty() {
 
 #[test]
 #[cfg(feature = "backtrace")]
-fn has_backtrace_depending_on_env() {
-    use std::env;
+fn has_backtrace_depending_on_policy() {
+    use error_chain::BacktracePolicy;
 
     error_chain! {
         types {}
@@ -214,24 +218,47 @@ fn has_backtrace_depending_on_env() {
         }
     }
 
-    let original_value = env::var_os("RUST_BACKTRACE");
+    let original_policy = error_chain::backtrace_policy();
 
-    // missing RUST_BACKTRACE and RUST_BACKTRACE=0
-    env::remove_var("RUST_BACKTRACE");
+    error_chain::set_backtrace_policy(BacktracePolicy::Never);
+    assert_eq!(error_chain::backtrace_policy(), BacktracePolicy::Never);
     let err = Error::from(ErrorKind::MyError);
     assert!(err.backtrace().is_none());
-    env::set_var("RUST_BACKTRACE", "0");
+
+    error_chain::set_backtrace_policy(BacktracePolicy::Always);
+    let err = Error::from(ErrorKind::MyError);
+    // The symbols are resolved when the backtrace is accessed.
+    let backtrace = err.backtrace().unwrap();
+    assert!(backtrace.frames().iter().any(|frame| !frame.symbols().is_empty()));
+
+    error_chain::set_backtrace_policy(BacktracePolicy::Sampled(0));
     let err = Error::from(ErrorKind::MyError);
     assert!(err.backtrace().is_none());
 
-    // RUST_BACKTRACE set to anything but 0
-    env::set_var("RUST_BACKTRACE", "yes");
+    error_chain::set_backtrace_policy(BacktracePolicy::Sampled(1));
+    assert_eq!(error_chain::backtrace_policy(), BacktracePolicy::Sampled(1));
     let err = Error::from(ErrorKind::MyError);
     assert!(err.backtrace().is_some());
 
-    if let Some(var) = original_value {
-        env::set_var("RUST_BACKTRACE", var);
-    }
+    // Each link records its own backtrace.
+    error_chain::set_backtrace_policy(BacktracePolicy::Always);
+    let inner: Result<()> = Err(ErrorKind::MyError.into());
+    let err = inner.chain_err(|| "outer").unwrap_err();
+    assert!(err.backtrace().is_some());
+    assert!(err.link_backtrace().is_none());
+    assert!(!err.display_chain().all_backtraces(true).to_string().contains("Backtrace of"));
+
+    error_chain::set_link_backtraces(true);
+    let inner: Result<()> = Err(ErrorKind::MyError.into());
+    let err = inner.chain_err(|| "outer").unwrap_err();
+    error_chain::set_link_backtraces(false);
+    assert!(err.link_backtrace().is_some());
+    let report = err.display_chain().all_backtraces(true).to_string();
+    assert!(report.starts_with("Error: outer\nCaused by: MyError\nBacktrace of \"outer\":\n"));
+    assert!(report.contains("\nBacktrace of the earliest error:\nstack backtrace:\n"));
+    assert!(!err.display_chain().to_string().contains("Backtrace of"));
+
+    error_chain::set_backtrace_policy(original_policy);
 }
 
 #[test]
@@ -251,6 +278,66 @@ fn chain_err() {
     let _: Result<()> = Err(Error::from_kind(ErrorKind::Test)).chain_err(|| "");
 }
 
+#[test]
+fn chain_err_with_context() {
+    use std::fmt;
+
+    error_chain! {
+        foreign_links {
+            Fmt(fmt::Error);
+        }
+        errors { Inner Outer }
+    }
+
+    let path = "/tmp/file";
+    let user = 42;
+    let inner: Result<()> = Err(fmt::Error)
+        .chain_err_with(|| ErrorKind::Inner, &[("path", &path), ("user", &user)]);
+    let err = inner.chain_err_with(|| ErrorKind::Outer, &[("request", &"abc")]).unwrap_err();
+
+    assert_eq!(err.context(), &[("request", "abc".to_string())]);
+    assert_eq!(err.context_value("request"), Some("abc"));
+    assert_eq!(err.context_value("path"), None);
+    assert!(format!("{:?}", err).contains("\"/tmp/file\""));
+    assert_eq!(format!("{}", err.display_chain().backtrace(false)),
+               "Error: Outer\n    request: abc\n\
+                Caused by: Inner\n    path: /tmp/file\n    user: 42\n\
+                Caused by: an error occurred when formatting an argument\n");
+}
+
+#[test]
+fn ok_or_chain() {
+    error_chain! {
+        errors {
+            Missing(name: &'static str)
+        }
+    }
+
+    let found: Result<u8> = Some(1).ok_or_chain(|| ErrorKind::Missing("found"));
+    assert_eq!(found.unwrap(), 1);
+
+    let missing: Result<u8> = None.ok_or_chain(|| ErrorKind::Missing("missing"));
+    match missing {
+        Err(Error(ErrorKind::Missing("missing"), _)) => {}
+        _ => panic!("expected `Missing`"),
+    }
+
+    let msg: Result<u8> = None.ok_or_chain(|| "missing");
+    assert_eq!(msg.unwrap_err().to_string(), "missing");
+}
+
+#[test]
+fn custom_option_ext() {
+    error_chain! {
+        types {
+            MyError, MyErrorKind, MyResultExt, MyOptionExt, MyResult;
+        }
+    }
+
+    let missing: MyResult<()> = None.ok_or_chain(|| "missing");
+    assert!(missing.is_err());
+}
+
 #[test]
 fn links() {
     mod test {
@@ -282,6 +369,8 @@ mod foreign_link_test {
         }
 
         fn cause(&self) -> Option<&::std::error::Error> { Some(&self.cause) }
+
+        fn source(&self) -> Option<&(::std::error::Error + 'static)> { Some(&self.cause) }
     }
 
     impl fmt::Display for ForeignError {
@@ -505,6 +594,52 @@ fn error_first() {
     }
 }
 
+#[test]
+fn kind_name_and_code() {
+    mod inner {
+        error_chain! {}
+    }
+
+    error_chain! {
+        links {
+            Inner(inner::Error, inner::ErrorKind);
+        }
+        foreign_links {
+            Io(::std::io::Error);
+        }
+        errors {
+            HttpStatus(e: u32) {
+                description("http request returned an unsuccessful status code")
+                display("http request returned an unsuccessful status code: {}", e)
+                code("E0404")
+            }
+            Timeout {
+                code("E0408")
+                description("timeout")
+            }
+            Unknown
+        }
+    }
+
+    assert_eq!(ErrorKind::HttpStatus(404).name(), "HttpStatus");
+    assert_eq!(ErrorKind::HttpStatus(404).code(), Some("E0404"));
+    assert_eq!(ErrorKind::HttpStatus(404).description(),
+               "http request returned an unsuccessful status code");
+    assert_eq!(ErrorKind::Timeout.name(), "Timeout");
+    assert_eq!(ErrorKind::Timeout.code(), Some("E0408"));
+    assert_eq!(ErrorKind::Timeout.description(), "timeout");
+    assert_eq!(ErrorKind::Unknown.name(), "Unknown");
+    assert_eq!(ErrorKind::Unknown.code(), None);
+
+    assert_eq!(ErrorKind::from("msg").name(), "Msg");
+    assert_eq!(ErrorKind::from("msg").code(), None);
+    assert_eq!(ErrorKind::Inner(inner::ErrorKind::from("msg")).name(), "Inner");
+    let io = ::std::io::Error::new(::std::io::ErrorKind::Other, "io");
+    assert_eq!(ErrorKind::Io(io).name(), "Io");
+
+    assert_eq!(Error::from(ErrorKind::Timeout).name(), "Timeout");
+}
+
 #[test]
 fn bail() {
     error_chain! {
@@ -523,3 +658,948 @@ fn bail() {
         bail!("{}", "baz")
     }
 }
+
+#[test]
+fn bail_other_errors() {
+    fn foo() -> Result<(), String> {
+        bail!("foo")
+    }
+
+    fn bar(x: i32) -> Result<(), String> {
+        ensure!(x != 0, "bar {}", x);
+        Ok(())
+    }
+
+    fn baz() -> Result<(), Box<::std::error::Error>> {
+        bail!("baz")
+    }
+
+    fn generic<E: From<String>>(x: i32) -> Result<(), E> {
+        bail!("generic {}", x)
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct StaticError(&'static str);
+
+    impl From<&'static str> for StaticError {
+        fn from(s: &'static str) -> StaticError {
+            StaticError(s)
+        }
+    }
+
+    fn custom(x: i32) -> Result<(), StaticError> {
+        ensure!(x != 0, "zero");
+        bail!("custom")
+    }
+
+    assert_eq!(foo(), Err("foo".to_string()));
+    assert_eq!(bar(0), Err("bar 0".to_string()));
+    assert_eq!(baz().unwrap_err().to_string(), "baz");
+    assert_eq!(generic::<String>(1), Err("generic 1".to_string()));
+    assert_eq!(custom(0), Err(StaticError("zero")));
+    assert_eq!(custom(1), Err(StaticError("custom")));
+}
+
+#[test]
+fn bail_location() {
+    error_chain! {
+        errors { Foo }
+    }
+
+    fn foo() -> Result<()> { bail!(ErrorKind::Foo) }
+    let foo_line = line!() - 1;
+
+    fn bar() -> Result<()> { ensure!(false, "bar {}", 1); Ok(()) }
+    let bar_line = line!() - 1;
+
+    let location = foo().unwrap_err().location().unwrap();
+    assert_eq!(location.file, file!());
+    assert_eq!(location.line, foo_line);
+    assert_eq!(location.column, 30);
+
+    let location = bar().unwrap_err().location().unwrap();
+    assert_eq!(location.line, bar_line);
+
+    assert_eq!(Error::from(ErrorKind::Foo).location(), None);
+}
+
+#[test]
+fn chain_err_location() {
+    use std::fmt;
+
+    error_chain! {
+        foreign_links {
+            Fmt(fmt::Error);
+        }
+    }
+
+    fn inner() -> Result<()> { bail!("inner") }
+    let inner_line = line!() - 1;
+
+    let err = chain_err!(inner(), "outer {}", 1).unwrap_err();
+    let outer_line = line!() - 1;
+    assert_eq!(err.location().unwrap().line, outer_line);
+    assert_eq!(format!("{}", err.display_chain().backtrace(false)),
+               format!("Error: outer 1\n    at {}:{}:15\nCaused by: inner\n    at {}:{}:32\n",
+                       file!(), outer_line, file!(), inner_line));
+    assert_eq!(Error::from("inner").location(), None);
+
+    let err: Error = chain_err!(Err::<(), _>(fmt::Error), "formatting failed").unwrap_err();
+    assert!(err.location().is_some());
+}
+
+#[test]
+fn ensure() {
+    error_chain! {
+        errors { Bar }
+    }
+
+    fn foo(x: u8) -> Result<()> {
+        ensure!(x == 42, ErrorKind::Bar);
+        Ok(())
+    }
+
+    fn bar(x: u8) -> Result<()> {
+        ensure!(x < 10, "x is too big: {}", x);
+        Ok(())
+    }
+
+    fn baz(x: u8) -> Result<()> {
+        ensure!(x != 0, "x is zero");
+        Ok(())
+    }
+
+    assert!(foo(42).is_ok());
+    match foo(0) {
+        Err(Error(ErrorKind::Bar, _)) => {}
+        _ => panic!("foo(0) should fail with `Bar`"),
+    }
+
+    assert!(bar(3).is_ok());
+    match bar(12) {
+        Err(Error(ErrorKind::Msg(ref msg), _)) => assert_eq!(msg, "x is too big: 12"),
+        _ => panic!("bar(12) should fail with a message"),
+    }
+
+    assert!(baz(1).is_ok());
+    match baz(0) {
+        Err(Error(ErrorKind::Msg(ref msg), _)) => assert_eq!(msg, "x is zero"),
+        _ => panic!("baz(0) should fail with a message"),
+    }
+}
+
+#[test]
+fn display_chain() {
+    error_chain! {
+        errors { Inner Outer Top }
+    }
+
+    let inner: Result<()> = Err(ErrorKind::Inner.into());
+    let outer = inner.chain_err(|| ErrorKind::Outer);
+    let err = outer.chain_err(|| ErrorKind::Top).unwrap_err();
+
+    assert_eq!(format!("{}", err.display_chain().backtrace(false)),
+               "Error: Top\nCaused by: Outer\nCaused by: Inner\n");
+    assert_eq!(format!("{}", err.display_chain().backtrace(false).indent(2)),
+               "Error: Top\n  Caused by: Outer\n    Caused by: Inner\n");
+    assert_eq!(format!("{}", err.display_chain().backtrace(false).max_depth(1)),
+               "Error: Top\nCaused by: Outer\n... and 1 more\n");
+    assert_eq!(format!("{}", err.display_chain().backtrace(false).max_depth(0)),
+               "Error: Top\n... and 2 more\n");
+}
+
+#[test]
+fn backtrace_filter() {
+    use error_chain::BacktraceFilter;
+
+    let filter = BacktraceFilter::default();
+    assert!(!filter.is_allowed("error_chain::make_backtrace"));
+    assert!(!filter.is_allowed("<error_chain::State as core::default::Default>::default"));
+    assert!(!filter.is_allowed("std::rt::lang_start"));
+    assert!(filter.is_allowed("tests::backtrace_filter"));
+
+    // v0 and legacy symbols
+    assert!(!filter.is_allowed("std[e28293b1aa0f68bd]::rt::lang_start_internal"));
+    assert!(!filter.is_allowed("<&dyn core[c1f1a4ba060b9bfa]::ops::function::Fn<()>>::call"));
+    assert!(!filter.is_allowed("std::rt::lang_start::h0123456789abcdef"));
+    assert!(filter.is_allowed("tests[0123abcd]::backtrace_filter"));
+
+    // Generic parameters are matched by their trait.
+    assert!(!filter.is_allowed("<T as core::convert::Into<U>>::into"));
+    assert!(filter.is_allowed("<app::Handler as core::ops::Drop>::drop"));
+    assert!(!filter.is_allowed("<fn() -> core::result::Result<(), app::Error> as \
+                                 core::ops::function::FnOnce<()>>::call_once"));
+
+    // The methods of the generated errors, which are in the user's crate.
+    assert!(!filter.is_allowed("app::errors::Error::from_kind"));
+    assert!(!filter.is_allowed("<app::errors::Error as \
+                                 core::convert::From<alloc::borrow::Cow<str>>>::from"));
+    assert!(!filter.is_allowed("<app::errors::Error as error_chain::ChainedError>::new"));
+    assert!(!filter.is_allowed("<core::result::Result<T, E> as \
+                                 app::errors::ResultExt<T>>::chain_err::h0123456789abcdef"));
+    assert!(filter.is_allowed("app::errors::from_kind_helper"));
+    assert!(BacktraceFilter::empty().is_allowed("app::errors::Error::from_kind"));
+
+    let filter = filter.deny("tests::").allow("std::thread::");
+    assert!(!filter.is_allowed("tests::backtrace_filter"));
+    assert!(filter.is_allowed("std::thread::spawn"));
+    assert!(BacktraceFilter::empty().is_allowed("std::rt::lang_start"));
+}
+
+#[test]
+#[cfg(feature = "backtrace")]
+fn boxed_errors() {
+    use std::error::Error as StdError;
+    use std::io;
+    use std::sync::Arc;
+    use error_chain::{ChainedError, InternalBacktrace};
+
+    mod other {
+        error_chain! {}
+    }
+
+    error_chain! {}
+
+    let mut inner = other::Error::from("inner");
+    inner.state_mut().backtrace = Some(Arc::new(InternalBacktrace::new()));
+    let err = Err::<(), _>(inner).chain_err(|| "outer").unwrap_err();
+    // The backtrace of an error which is not from `links` is reused too.
+    assert_eq!(err.backtrace().map(|b| b as *const _),
+               err.iter().nth(1).and_then(error_chain::extract_backtrace).map(|b| b as *const _));
+
+    let boxed: Box<StdError + Send> = err.into();
+    assert_eq!(boxed.to_string(), "outer");
+    assert_eq!(boxed.source().unwrap().to_string(), "inner");
+    assert!(error_chain::extract_backtrace(&*boxed).is_some());
+    assert!(error_chain::extract_backtrace(boxed.source().unwrap()).is_some());
+
+    let e = io::Error::new(io::ErrorKind::Other, "foreign");
+    assert!(error_chain::extract_backtrace(&e).is_none());
+}
+
+#[test]
+fn boxed_sync_errors() {
+    use std::error::Error as StdError;
+
+    error_chain! {
+        options {
+            sync;
+        }
+    }
+
+    fn plugin() -> ::std::result::Result<(), Box<StdError + Send + Sync>> {
+        Err(Error::from("plugin failed"))?
+    }
+
+    let e = plugin().unwrap_err();
+    assert_eq!(e.to_string(), "plugin failed");
+    assert!(e.downcast_ref::<Error>().is_some());
+}
+
+#[test]
+fn find_causes() {
+    use std::io;
+
+    mod inner {
+        error_chain! {}
+    }
+
+    error_chain! {
+        links {
+            Inner(inner::Error, inner::ErrorKind);
+        }
+    }
+
+    let io_error = io::Error::new(io::ErrorKind::NotFound, "not found");
+    let inner: inner::Result<()> = inner::ResultExt::chain_err(Err(io_error), || "inner");
+    let err = inner.chain_err(|| "outer").unwrap_err();
+
+    assert_eq!(err.depth(), 3);
+    assert_eq!(err.root_cause().to_string(), "not found");
+    assert_eq!(err.find_cause::<io::Error>().unwrap().kind(), io::ErrorKind::NotFound);
+    assert_eq!(err.find_cause::<inner::Error>().unwrap().to_string(), "inner");
+    assert_eq!(err.find_cause::<Error>().unwrap().to_string(), "outer");
+    assert!(err.find_cause::<::std::fmt::Error>().is_none());
+    assert!(err.any_cause(|e| e.is::<io::Error>()));
+    assert!(!err.any_cause(|e| e.to_string() == "missing"));
+
+    let mut iter = err.iter();
+    iter.next();
+    assert_eq!(iter.root_cause().unwrap().to_string(), "not found");
+    assert!(err.iter().any_cause(|e| e.is::<inner::Error>()));
+
+    let leaf = Error::from("leaf");
+    assert_eq!(leaf.depth(), 1);
+    assert_eq!(leaf.root_cause().to_string(), "leaf");
+}
+
+#[test]
+fn chained_links() {
+    mod inner {
+        error_chain! {
+            errors {
+                Disk {
+                    description("disk error")
+                    display("disk is full")
+                }
+            }
+        }
+    }
+
+    error_chain! {
+        links {
+            Inner(inner::Error, inner::ErrorKind) chained;
+        }
+    }
+
+    fn fails() -> inner::Result<()> {
+        let r: ::std::result::Result<(), ::std::fmt::Error> = Err(::std::fmt::Error);
+        inner::ResultExt::chain_err_with(r, || inner::ErrorKind::Disk, &[("path", &"/tmp")])
+    }
+
+    let err = Error::from(fails().unwrap_err());
+    match *err.kind() {
+        ErrorKind::Inner(inner::ErrorKind::Disk) => {}
+        _ => panic!("unexpected kind: {:?}", err.kind()),
+    }
+    assert_eq!(err.depth(), 3);
+    let linked = err.find_cause::<error_chain::LinkedError>().unwrap();
+    assert_eq!(linked.to_string(), "disk is full");
+    assert_eq!(linked.context(), &[("path", "/tmp".to_string())][..]);
+    assert!(err.find_cause::<::std::fmt::Error>().is_some());
+
+    let report = err.display_chain().backtrace(false).to_string();
+    assert_eq!(report,
+               "Error: disk is full\n\
+                Caused by: disk is full\n    path: /tmp\n\
+                Caused by: an error occurred when formatting an argument\n");
+}
+
+#[test]
+fn aggregate_errors() {
+    error_chain! {}
+
+    fn parse(s: &str) -> Result<u32> {
+        s.parse().chain_err(|| format!("invalid number: {}", s))
+    }
+
+    let values = Error::collect_all(vec!["1", "2"].into_iter().map(parse), || "unused");
+    assert_eq!(values.unwrap(), vec![1, 2]);
+
+    let err = Error::collect_all(vec!["1", "a", "-1"].into_iter().map(parse),
+                                 || "validation failed")
+        .unwrap_err();
+    assert_eq!(err.to_string(), "validation failed");
+    let aggregate = err.find_cause::<error_chain::AggregateError>().unwrap();
+    assert_eq!(aggregate.len(), 2);
+    let messages: Vec<_> = aggregate.iter().map(|e| e.to_string()).collect();
+    assert_eq!(messages, ["invalid number: a", "invalid number: -1"]);
+    assert!(aggregate.iter().all(|e| e.is::<Error>()));
+
+    let report = err.display_chain().backtrace(false).to_string();
+    assert_eq!(report,
+               "Error: validation failed\n\
+                Caused by (1/2): invalid number: a\n    \
+                Caused by: invalid digit found in string\n\
+                Caused by (2/2): invalid number: -1\n    \
+                Caused by: invalid digit found in string\n");
+    assert_eq!(format!("{}", err.display_chain().backtrace(false).max_depth(1)),
+               "Error: validation failed\n\
+                Caused by (1/2): invalid number: a\n    \
+                ... and 1 more\n\
+                Caused by (2/2): invalid number: -1\n    \
+                ... and 1 more\n");
+    assert_eq!(format!("{}", err.display_chain().backtrace(false).max_depth(0)),
+               "Error: validation failed\n... and 1 more\n");
+
+    let err = Error::aggregate("empty", Vec::<Error>::new());
+    assert!(err.find_cause::<error_chain::AggregateError>().unwrap().is_empty());
+}
+
+#[cfg(test)]
+mod exit_code_test {
+    use error_chain::ChainedError;
+
+    mod inner {
+        error_chain! {
+            errors {
+                Usage {
+                    exit_code(64)
+                }
+            }
+        }
+    }
+
+    error_chain! {
+        links {
+            Inner(inner::Error, inner::ErrorKind);
+        }
+        errors {
+            Config(path: String) {
+                display("invalid config: {}", path)
+                exit_code(78)
+            }
+        }
+    }
+
+    quick_main!(run);
+
+    fn run() -> Result<()> {
+        bail!(ErrorKind::Config("app.toml".to_string()))
+    }
+
+    #[test]
+    fn exit_codes() {
+        assert_eq!(ErrorKind::Config("app.toml".to_string()).exit_code(), 78);
+        assert_eq!(ErrorKind::from("msg").exit_code(), 1);
+        assert_eq!(ErrorKind::Inner(inner::ErrorKind::Usage).exit_code(), 64);
+        assert_eq!(ErrorKind::Inner(inner::ErrorKind::from("msg")).exit_code(), 1);
+        assert_eq!(run().unwrap_err().exit_code(), 78);
+    }
+
+    #[test]
+    fn report_main() {
+        assert_eq!(::error_chain::report_main(Ok::<(), Error>(())), 0);
+        assert_eq!(::error_chain::report_main(run()), 78);
+    }
+}
+
+#[test]
+#[cfg(feature = "log")]
+fn log_errors() {
+    use std::sync::Mutex;
+
+    struct Logger(Mutex<Vec<(log::Level, String, String)>>);
+
+    impl log::Log for Logger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            let entry = (record.level(), record.target().to_string(), record.args().to_string());
+            self.0.lock().unwrap().push(entry);
+        }
+
+        fn flush(&self) {}
+    }
+
+    error_chain! {}
+
+    let logger: &'static Logger = Box::leak(Box::new(Logger(Mutex::new(Vec::new()))));
+    log::set_logger(logger).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let inner: Result<()> = Err("inner".into());
+    let result = inner.chain_err(|| "outer").log_err(log::Level::Warn);
+    assert_eq!(result.unwrap_err().to_string(), "outer");
+
+    let ok: Result<u32> = Ok(1);
+    assert_eq!(ok.log_err(log::Level::Warn).unwrap(), 1);
+
+    let err = Error::from("alone");
+    err.log(log::Level::Error);
+    assert_eq!(err.to_string(), "alone");
+
+    let records = logger.0.lock().unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].0, log::Level::Warn);
+    assert_eq!(records[0].1, module_path!());
+    assert!(records[0].2.starts_with("Error: outer\nCaused by: inner"));
+    assert_eq!(records[1].0, log::Level::Error);
+    assert!(records[1].2.starts_with("Error: alone"));
+}
+
+#[test]
+fn clone_errors() {
+    mod inner {
+        error_chain! {
+            options {
+                clone;
+            }
+        }
+    }
+
+    error_chain! {
+        options {
+            clone;
+        }
+        links {
+            Inner(inner::Error, inner::ErrorKind);
+        }
+        foreign_links {
+            Fmt(::std::fmt::Error);
+        }
+        errors {
+            Status(code: u32) {
+                display("status: {}", code)
+            }
+        }
+    }
+
+    let inner: Result<()> = Err(::std::fmt::Error.into());
+    let err = inner.chain_err_with(|| ErrorKind::Status(500), &[("attempt", &1)]).unwrap_err();
+    let copy = err.clone();
+    assert_eq!(copy.to_string(), err.to_string());
+    assert_eq!(copy.depth(), 2);
+    assert_eq!(copy.context_value("attempt"), Some("1"));
+    assert_eq!(copy.backtrace().is_some(), err.backtrace().is_some());
+    match *copy.kind() {
+        ErrorKind::Status(500) => {}
+        _ => panic!("unexpected kind: {:?}", copy.kind()),
+    }
+    let cause = |e: &Error| e.iter().nth(1).unwrap() as *const _ as *const u8;
+    assert_eq!(cause(&copy), cause(&err));
+
+    let inner = inner::Error::from("inner");
+    assert_eq!(Error::from(inner.clone()).to_string(), inner.to_string());
+}
+
+#[test]
+fn clone_and_default_errors() {
+    mod shared {
+        error_chain! {
+            options {
+                clone;
+            }
+            foreign_links {
+                Fmt(::std::fmt::Error);
+            }
+        }
+    }
+
+    error_chain! {
+        links {
+            Shared(shared::Error, shared::ErrorKind);
+        }
+    }
+
+    let inner: shared::Result<()> = shared::ResultExt::chain_err(Err(::std::fmt::Error), || "fmt");
+    let shared = shared::ResultExt::chain_err(inner, || "shared").unwrap_err();
+    let err = Error::from(shared.clone());
+    let chain: Vec<_> = err.iter().map(|e| e.to_string()).collect();
+    assert_eq!(chain, ["shared", "fmt", "an error occurred when formatting an argument"]);
+    assert_eq!(err.find_cause::<shared::Error>().unwrap().to_string(), "fmt");
+    assert!(err.find_cause::<::std::fmt::Error>().is_some());
+    assert_eq!(err.backtrace().is_some(), shared.backtrace().is_some());
+}
+
+#[cfg(test)]
+mod thin_test {
+    use std::mem::size_of;
+
+    mod inner {
+        error_chain! {
+            options {
+                thin;
+            }
+            errors {
+                Disk {
+                    display("disk is full")
+                }
+            }
+        }
+    }
+
+    mod outer {
+        error_chain! {
+            links {
+                Inner(super::inner::Error, super::inner::ErrorKind);
+            }
+        }
+    }
+
+    error_chain! {
+        options {
+            thin;
+        }
+        links {
+            Inner(inner::Error, inner::ErrorKind) chained;
+            Outer(outer::Error, outer::ErrorKind);
+        }
+    }
+
+    #[test]
+    fn thin_errors() {
+        assert_eq!(size_of::<Error>(), size_of::<usize>());
+        assert_eq!(size_of::<Result<()>>(), size_of::<usize>());
+
+        let inner: Result<()> = Err("inner".into());
+        let err = inner.chain_err(|| "outer").unwrap_err();
+        assert_eq!(err.to_string(), "outer");
+        assert_eq!(err.depth(), 2);
+        match *err.kind() {
+            ErrorKind::Msg(ref s) => assert_eq!(s, "outer"),
+            _ => panic!("unexpected kind: {:?}", err.kind()),
+        }
+    }
+
+    #[test]
+    fn thin_links() {
+        let inner = inner::Error::from_kind(inner::ErrorKind::Disk);
+        let err = Error::from(outer::Error::from(inner));
+        match *err.kind() {
+            ErrorKind::Outer(outer::ErrorKind::Inner(inner::ErrorKind::Disk)) => {}
+            _ => panic!("unexpected kind: {:?}", err.kind()),
+        }
+
+        let err = Error::from(inner::Error::from_kind(inner::ErrorKind::Disk));
+        assert_eq!(err.depth(), 2);
+        assert!(err.find_cause::<::error_chain::LinkedError>().is_some());
+        assert_eq!(ErrorKind::from(err).name(), "Inner");
+    }
+}
+
+#[test]
+fn static_messages() {
+    use std::borrow::Cow;
+
+    error_chain! {}
+
+    let r: ::std::result::Result<(), ::std::fmt::Error> = Err(::std::fmt::Error);
+    match *chain_err!(r, "static cause").unwrap_err().kind() {
+        ErrorKind::Msg(Cow::Borrowed(s)) => assert_eq!(s, "static cause"),
+        ref kind => panic!("unexpected kind: {:?}", kind),
+    }
+
+    // Other strings are copied, even the literals returned to `chain_err`.
+    let r: ::std::result::Result<(), ::std::fmt::Error> = Err(::std::fmt::Error);
+    match *r.chain_err(|| "closure cause").unwrap_err().kind() {
+        ErrorKind::Msg(Cow::Owned(ref s)) => assert_eq!(s, "closure cause"),
+        ref kind => panic!("unexpected kind: {:?}", kind),
+    }
+
+    fn borrowed(name: &str) -> Result<()> {
+        Err(name.into())
+    }
+
+    match *borrowed("borrowed").unwrap_err().kind() {
+        ErrorKind::Msg(Cow::Owned(ref s)) => assert_eq!(s, "borrowed"),
+        ref kind => panic!("unexpected kind: {:?}", kind),
+    }
+
+    let name = String::from("name");
+    let r: ::std::result::Result<(), ::std::fmt::Error> = Err(::std::fmt::Error);
+    match *r.chain_err(|| name.as_str()).unwrap_err().kind() {
+        ErrorKind::Msg(Cow::Owned(ref s)) => assert_eq!(s, "name"),
+        ref kind => panic!("unexpected kind: {:?}", kind),
+    }
+
+    match *Error::from(format!("{} message", "owned")).kind() {
+        ErrorKind::Msg(Cow::Owned(ref s)) => assert_eq!(s, "owned message"),
+        ref kind => panic!("unexpected kind: {:?}", kind),
+    }
+
+    let kind = ErrorKind::from(Cow::Borrowed("cow"));
+    assert_eq!(kind.to_string(), "cow");
+    assert_eq!(kind.description(), "cow");
+}
+
+#[test]
+fn custom_msg() {
+    error_chain! {
+        options {
+            msg(Message);
+        }
+    }
+
+    fn fails() -> Result<()> {
+        bail!("custom {}", "message")
+    }
+
+    let err = fails().unwrap_err();
+    assert_eq!(err.kind().name(), "Message");
+    match *err.kind() {
+        ErrorKind::Message(ref s) => assert_eq!(s, "custom message"),
+    }
+}
+
+#[test]
+fn no_msg() {
+    error_chain! {
+        options {
+            no_msg;
+        }
+        errors {
+            Invalid(n: i32) {
+                display("invalid: {}", n)
+            }
+        }
+    }
+
+    fn check(n: i32) -> Result<()> {
+        ensure!(n > 0, ErrorKind::Invalid(n));
+        let r: ::std::result::Result<(), ::std::fmt::Error> = Err(::std::fmt::Error);
+        r.chain_err(|| ErrorKind::Invalid(n))
+    }
+
+    match *check(0).unwrap_err().kind() {
+        ErrorKind::Invalid(0) => {}
+        ref kind => panic!("unexpected kind: {:?}", kind),
+    }
+    assert_eq!(check(1).unwrap_err().depth(), 2);
+}
+
+#[test]
+fn from_clauses() {
+    use std::num::ParseIntError;
+
+    pub struct Timeout;
+
+    error_chain! {
+        errors {
+            InvalidNumber(e: ParseIntError) {
+                display("invalid number: {}", e)
+                from()
+            }
+            TimedOut {
+                display("timed out")
+                from(Timeout)
+            }
+            Parse(input: String) {
+                display("cannot parse {}", input)
+                from(e: ::std::fmt::Error) -> (e.to_string())
+            }
+        }
+    }
+
+    fn parse(s: &str) -> Result<u32> {
+        Ok(s.parse::<u32>()?)
+    }
+
+    match *parse("a").unwrap_err().kind() {
+        ErrorKind::InvalidNumber(_) => {}
+        ref kind => panic!("unexpected kind: {:?}", kind),
+    }
+    assert_eq!(parse("12").unwrap(), 12);
+    assert_eq!(Error::from(Timeout).kind().name(), "TimedOut");
+    assert_eq!(Error::from(::std::fmt::Error).kind().name(), "Parse");
+}
+
+#[test]
+fn cause_clauses() {
+    use std::error::Error as StdError;
+    use std::num::ParseIntError;
+
+    mod inner {
+        error_chain! {
+            errors {
+                Wrapped(e: ::std::fmt::Error) {
+                    display("wrapped")
+                    cause(e)
+                }
+            }
+        }
+    }
+
+    error_chain! {
+        links {
+            Inner(inner::Error, inner::ErrorKind);
+        }
+        errors {
+            InvalidNumber(input: String, e: ParseIntError) {
+                display("invalid number: {}", input)
+                cause(e)
+            }
+        }
+    }
+
+    let e = "a".parse::<u32>().unwrap_err();
+    let err = Error::from(ErrorKind::InvalidNumber("a".to_string(), e.clone()));
+    assert!(err.kind().contained_error().is_some());
+    assert_eq!(err.source().unwrap().to_string(), e.to_string());
+    assert_eq!(err.cause().unwrap().to_string(), e.to_string());
+    assert_eq!(err.depth(), 2);
+    assert!(err.find_cause::<ParseIntError>().is_some());
+    assert!(Error::from("msg").kind().contained_error().is_none());
+
+    let err = Error::from(inner::Error::from(inner::ErrorKind::Wrapped(::std::fmt::Error)));
+    assert!(err.find_cause::<::std::fmt::Error>().is_some());
+
+    let r: ::std::result::Result<(), ::std::fmt::Error> = Err(::std::fmt::Error);
+    let err = r.chain_err(|| ErrorKind::InvalidNumber("b".to_string(), e)).unwrap_err();
+    assert!(err.find_cause::<::std::fmt::Error>().is_some());
+    assert!(err.find_cause::<ParseIntError>().is_none());
+}
+
+#[cfg(test)]
+mod assert_test {
+    error_chain! {
+        errors {
+            NotFound(name: String) {
+                display("not found: {}", name)
+            }
+        }
+    }
+
+    fn find(name: &str) -> Result<()> {
+        let r: ::std::result::Result<(), ::std::fmt::Error> = Err(::std::fmt::Error);
+        r.chain_err(|| ErrorKind::NotFound(name.to_string()))
+    }
+
+    #[test]
+    fn assert_err_kind() {
+        assert_err_kind!(find("foo"), ErrorKind::NotFound(..));
+        assert_err_kind!(Err::<(), Error>("foo".into()), ErrorKind::Msg(_));
+    }
+
+    #[test]
+    #[should_panic(expected = "expected an error of kind `ErrorKind::Msg(..)`, got:\n\
+                               Error: not found: foo\n\
+                               Caused by: an error occurred when formatting an argument")]
+    fn assert_err_kind_wrong_kind() {
+        assert_err_kind!(find("foo"), ErrorKind::Msg(..));
+    }
+
+    #[test]
+    #[should_panic(expected = "got `Ok`")]
+    fn assert_err_kind_ok() {
+        assert_err_kind!(Ok::<(), Error>(()), ErrorKind::Msg(..));
+    }
+
+    #[test]
+    fn assert_chain() {
+        let err = find("foo").unwrap_err();
+        assert_chain!(err, [
+            Error: "not found: foo",
+            ::std::fmt::Error: "an error occurred when formatting an argument",
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected error 1 of the chain to be a `Error` displayed as \
+                               \"other\", got:\nError: not found: foo")]
+    fn assert_chain_wrong_error() {
+        assert_chain!(find("foo").unwrap_err(), [Error: "not found: foo", Error: "other"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected 1 errors in the chain, got 2")]
+    fn assert_chain_too_long() {
+        assert_chain!(find("foo").unwrap_err(), [Error: "not found: foo"]);
+    }
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn serialize_snapshot() {
+    use std::error::Error as StdError;
+    use std::fmt;
+    use error_chain::ErrorSnapshot;
+
+    error_chain! {
+        foreign_links {
+            Fmt(fmt::Error);
+        }
+        errors {
+            HttpStatus(e: u32) {
+                description("http request returned an unsuccessful status code")
+                display("http request returned an unsuccessful status code: {}", e)
+            }
+        }
+    }
+
+    let inner: Result<()> = Err(fmt::Error).chain_err(|| "inner");
+    let err = inner.chain_err(|| ErrorKind::HttpStatus(404)).unwrap_err();
+
+    let snapshot = ErrorSnapshot::new(&err);
+    let json = serde_json::to_string(&snapshot).unwrap();
+    let restored: ErrorSnapshot = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored, snapshot);
+
+    assert_eq!(restored.kind(), "HttpStatus");
+    assert_eq!(restored.to_string(), err.to_string());
+    assert_eq!(restored.description(), err.description());
+    let restored_chain: Vec<_> = restored.iter().map(|e| e.to_string()).collect();
+    let original_chain: Vec<_> = err.iter().map(|e| e.to_string()).collect();
+    assert_eq!(restored_chain, original_chain);
+    assert_eq!(restored_chain.len(), 3);
+    assert_eq!(restored.backtrace().is_some(), err.backtrace().is_some());
+
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["causes"][0]["message"], "inner");
+}
+
+#[test]
+fn sync_errors() {
+    use std::error::Error as StdError;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn assert_sync<T: Send + Sync>() {}
+
+    error_chain! {
+        foreign_links {
+            Io(::std::io::Error);
+        }
+        options {
+            sync;
+        }
+    }
+
+    assert_sync::<error_chain::State<Box<StdError + Send + Sync>>>();
+    assert_sync::<Error>();
+    assert_sync::<ErrorKind>();
+
+    let inner: Result<()> = Err("inner".into());
+    let err = Arc::new(inner.chain_err(|| "outer").unwrap_err());
+    let shared = err.clone();
+    let handle = thread::spawn(move || shared.iter().count());
+    assert_eq!(handle.join().unwrap(), 2);
+
+    let inner: Result<()> = Err("inner".into());
+    let boxed: Box<StdError + Send + Sync> = Box::new(inner.unwrap_err());
+    assert_eq!(boxed.to_string(), "inner");
+}
+
+#[test]
+fn sync_and_default_errors() {
+    use std::cell::Cell;
+    use std::error::Error as StdError;
+    use std::fmt;
+
+    mod shared {
+        error_chain! {
+            options {
+                sync;
+            }
+        }
+    }
+
+    error_chain! {
+        links {
+            Shared(shared::Error, shared::ErrorKind);
+        }
+    }
+
+    // An error which is `Send` but not `Sync`.
+    #[derive(Debug)]
+    struct Unsync(Cell<u32>);
+
+    impl fmt::Display for Unsync {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "unsync {}", self.0.get())
+        }
+    }
+
+    impl StdError for Unsync {
+        fn description(&self) -> &str {
+            "unsync"
+        }
+    }
+
+    let unsync: ::std::result::Result<(), Unsync> = Err(Unsync(Cell::new(1)));
+    let err = unsync.chain_err(|| "outer").unwrap_err();
+    assert_eq!(err.iter().nth(1).unwrap().to_string(), "unsync 1");
+
+    let inner: shared::Result<()> = Err("inner".into());
+    let inner = shared::ResultExt::chain_err(inner, || "shared").unwrap_err();
+    let err = Error::from(inner);
+    match *err.kind() {
+        ErrorKind::Shared(shared::ErrorKind::Msg(ref msg)) => assert_eq!(msg, "shared"),
+        _ => panic!("unexpected kind"),
+    }
+    let chain: Vec<_> = err.iter().map(|e| e.to_string()).collect();
+    assert_eq!(chain, ["shared", "inner"]);
+    assert!(err.display_chain().backtrace(false).to_string().contains("Caused by: inner"));
+}